@@ -1,17 +1,26 @@
 pub mod keymap;
+pub mod platform;
+pub mod slots;
 
 use lazy_static::lazy_static;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
-use winit::platform::macos::WindowExtMacOS;
 use winit::{
-    application::ApplicationHandler, dpi::LogicalSize, event::WindowEvent,
-    platform::macos::WindowAttributesExtMacOS, window::Window,
+    application::ApplicationHandler,
+    dpi::LogicalSize,
+    event::WindowEvent,
+    keyboard::ModifiersState,
+    window::{Fullscreen, Window},
 };
 
 use crate::beeper::Beeper;
+use crate::debugger::Debugger;
+use crate::movie::{Player, Recorder};
+use crate::rewind::Rewind;
 use crate::settings::Settings;
-use crate::vm::Vm;
+use crate::trace::Tracer;
+use crate::vm::{Vm, VmError};
 use crate::wgpu_ctx::WgpuCtx;
 
 lazy_static! {
@@ -25,13 +34,30 @@ pub struct App<'window> {
     settings: Arc<RwLock<Settings>>,
     beeper: Beeper,
     last_frame_time: Instant,
+    halted: Option<VmError>,
+    modifiers: ModifiersState,
+    rewind: Rewind,
+    rewinding: bool,
+    debugger: Debugger,
+    tracer: Option<Tracer>,
+    recording: Option<(Recorder, PathBuf)>,
+    player: Option<Player>,
+    // what the window was last set to, changing it every frame makes X11 flicker
+    borderless: bool,
+    // how the window opens
+    window_size: LogicalSize<u32>,
+    fullscreen: bool,
 }
 
 impl App<'_> {
-    pub fn new(vm: Vm, settings: Arc<RwLock<Settings>>) -> Self {
-        let (beep_freq, scale) = {
+    pub fn new(vm: Vm, settings: Arc<RwLock<Settings>>, debugger: Debugger) -> Self {
+        let (beep_freq, scale, rewind_seconds) = {
             let settings = settings.read().unwrap();
-            (settings.beep_freq, settings.scale_mode)
+            (
+                settings.beep_freq,
+                settings.scale_mode,
+                settings.rewind_seconds,
+            )
         };
 
         let mut beeper = Beeper::new();
@@ -46,29 +72,94 @@ impl App<'_> {
             settings,
             beeper,
             last_frame_time: Instant::now(),
+            halted: None,
+            modifiers: ModifiersState::empty(),
+            rewind: Rewind::new(rewind_seconds),
+            rewinding: false,
+            debugger,
+            tracer: None,
+            recording: None,
+            player: None,
+            borderless: false,
+            window_size: LogicalSize::new(640 * 2, 320 * 2),
+            fullscreen: false,
+        }
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    // the movie is written to `path` when recording stops
+    pub fn set_recorder(&mut self, recorder: Recorder, path: PathBuf) {
+        self.recording = Some((recorder, path));
+    }
+
+    // in logical pixels, before the window opens
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.window_size = LogicalSize::new(width, height);
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+    }
+
+    // the keypad ignores the keyboard until the movie is over
+    pub fn set_player(&mut self, player: Player) {
+        self.player = Some(player);
+    }
+}
+
+impl App<'_> {
+    fn handle_slot(&mut self, slot: u8) {
+        if self.modifiers.shift_key() {
+            match slots::save(&self.vm, slot) {
+                Ok(path) => println!("Saved state to {}", path.display()),
+                Err(err) => eprintln!("Failed to save state {}: {}", slot, err),
+            }
+        } else {
+            match slots::load(&mut self.vm, slot) {
+                Ok(path) => {
+                    println!("Loaded state from {}", path.display());
+                    self.halted = None;
+                    stop_recording(&mut self.recording, "Recording stopped by loading a state");
+                    stop_replay(&mut self.player, "Replay stopped by loading a state");
+                }
+                Err(err) => eprintln!("Failed to load state {}: {}", slot, err),
+            }
         }
     }
 }
 
 impl ApplicationHandler for App<'_> {
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Some(wgpu_ctx) = self.wgpu_ctx.as_mut() {
+            wgpu_ctx.save_settings();
+        }
+    }
+
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         if self.window.is_some() {
             return;
         }
 
         let window = {
+            let borderless = self.settings.read().unwrap().borderless;
+            let title = match self.vm.rom_info() {
+                Some(info) => format!("{} - CHIP8", info.title),
+                None => "CHIP8".to_string(),
+            };
             let window_attrs = Window::default_attributes()
-                .with_inner_size(LogicalSize::new(640 * 2, 320 * 2))
+                .with_inner_size(self.window_size)
+                .with_fullscreen(self.fullscreen.then_some(Fullscreen::Borderless(None)))
                 .with_resizable(true)
-                .with_titlebar_transparent(true)
-                .with_fullsize_content_view(true)
-                .with_title_hidden(true)
-                .with_has_shadow(true)
                 .with_transparent(true)
-                .with_title("CHIP8");
+                .with_decorations(!borderless)
+                .with_title(title);
+            self.borderless = borderless;
 
             event_loop
-                .create_window(window_attrs)
+                .create_window(platform::window_attributes(window_attrs))
                 .expect("Failed to create window")
         };
 
@@ -95,44 +186,144 @@ impl ApplicationHandler for App<'_> {
                 if let (Some(window), Some(wgpu_ctx)) = (&self.window, self.wgpu_ctx.as_mut()) {
                     let (
                         ticks_per_frame,
+                        quirks,
                         show_settings,
                         beep_freqency,
                         window_has_shadow,
+                        borderless,
                         scale_mode,
+                        rewind_seconds,
                     ) = {
                         let settings = self.settings.read().unwrap();
                         (
                             settings.ticks_per_frame,
+                            settings.quirks,
                             settings.show_settings,
                             settings.beep_freq,
                             settings.window_has_shadow,
+                            settings.borderless,
                             settings.scale_mode,
+                            settings.rewind_seconds,
                         )
                     };
 
-                    window.set_has_shadow(window_has_shadow);
+                    platform::set_has_shadow(window, window_has_shadow);
+                    if borderless != self.borderless {
+                        window.set_decorations(!borderless);
+                        self.borderless = borderless;
+                    }
+                    self.vm.set_quirks(quirks);
+                    self.rewind.set_seconds(rewind_seconds);
+                    self.vm.set_track_accesses(self.debugger.needs_accesses());
 
                     self.beeper.set_scale_mode(scale_mode);
                     if !scale_mode {
                         self.beeper.set_freq(beep_freqency);
                     }
 
-                    if !show_settings {
+                    let changed = self
+                        .recording
+                        .as_ref()
+                        .is_some_and(|(recorder, _)| !recorder.matches(quirks, ticks_per_frame));
+                    if changed {
+                        stop_recording(
+                            &mut self.recording,
+                            "Recording stopped by a change of speed or quirks",
+                        );
+                    }
+
+                    if !show_settings && self.rewinding {
+                        if self.rewind.step_back(&mut self.vm) {
+                            self.halted = None;
+                            if let Some((recorder, _)) = &mut self.recording {
+                                recorder.rewind_frame();
+                            }
+                            stop_replay(&mut self.player, "Replay stopped by rewinding");
+                        }
+                        self.beeper.pause();
+                    } else if !show_settings && self.halted.is_none() {
+                        if let Some(player) = &mut self.player {
+                            player.apply(&mut self.vm);
+                        }
+
+                        let mut executed = 0;
                         for _ in 0..ticks_per_frame {
-                            self.vm.tick();
+                            if !self.debugger.before_tick(&self.vm) {
+                                break;
+                            }
+
+                            if let Some(tracer) = &mut self.tracer {
+                                tracer.before_tick(&self.vm);
+                            }
+
+                            if let Err(err) = self.vm.tick() {
+                                eprintln!("VM halted: {}", err);
+                                self.halted = Some(err);
+                                break;
+                            }
+
+                            let traced = self
+                                .tracer
+                                .as_mut()
+                                .map(|tracer| tracer.after_tick(&self.vm));
+                            if let Some(Err(err)) = traced {
+                                eprintln!("Trace stopped: {}", err);
+                                self.tracer = None;
+                            }
+
+                            self.debugger.after_tick(&self.vm);
+                            executed += 1;
+                        }
+
+                        if let Some(reason) = self.debugger.take_stop() {
+                            println!("Debugger: {}, pc 0x{:03x}", reason, self.vm.pc());
                         }
 
-                        if self.vm.st > 0 {
-                            self.beeper.play();
+                        // a frame cut short by the debugger doesn't advance the timers
+                        if executed == ticks_per_frame {
+                            if self.vm.st() > 0 {
+                                self.beeper.play();
+                            } else {
+                                self.beeper.pause();
+                            }
+
+                            self.vm.delay_timer();
+                            self.vm.sound_timer();
+
+                            self.rewind.push(&self.vm);
+
+                            if let Some((recorder, _)) = &mut self.recording {
+                                recorder.end_frame();
+                            }
+                            let replayed = self.player.as_mut().map(|player| {
+                                player.end_frame();
+                                player.finished()
+                            });
+                            if replayed == Some(true) {
+                                stop_replay(&mut self.player, "Replay finished");
+                            }
                         } else {
                             self.beeper.pause();
+
+                            // the rest of the frame runs after the pause, which
+                            // a movie can't replay
+                            if executed > 0 && self.halted.is_none() {
+                                stop_recording(
+                                    &mut self.recording,
+                                    "Recording stopped by the debugger",
+                                );
+                            }
                         }
+                    }
 
-                        self.vm.delay_timer();
-                        self.vm.sound_timer();
+                    if self.halted.is_some() {
+                        self.beeper.pause();
                     }
 
-                    wgpu_ctx.draw(&self.vm.vb);
+                    wgpu_ctx.draw(&mut self.vm, &mut self.debugger, self.halted.as_ref());
+                    if self.debugger.take_edited() {
+                        stop_recording(&mut self.recording, "Recording stopped by a debugger edit");
+                    }
                     window.request_redraw();
 
                     let elapsed = self.last_frame_time.elapsed();
@@ -145,6 +336,7 @@ impl ApplicationHandler for App<'_> {
             }
 
             WindowEvent::CloseRequested => {
+                stop_recording(&mut self.recording, "Recording stopped");
                 event_loop.exit();
             }
 
@@ -155,12 +347,31 @@ impl ApplicationHandler for App<'_> {
                 }
             }
 
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+
             WindowEvent::KeyboardInput { event, .. } => {
-                use keymap::KEYMAP;
+                use keymap::{
+                    BREAKPOINT_KEY, DEBUGGER_KEY, PAUSE_KEY, REWIND_KEY, SLOT_KEYS, STEP_INTO_KEY,
+                    STEP_OVER_KEY,
+                };
                 use winit::event::ElementState;
                 use winit::keyboard::KeyCode;
 
+                // typing into a debugger field shouldn't press CHIP-8 keys, releases
+                // still go through so nothing is left held down
+                let typing = event.state == ElementState::Pressed
+                    && self
+                        .wgpu_ctx
+                        .as_ref()
+                        .is_some_and(|ctx| ctx.egui_renderer.context().wants_keyboard_input());
+                if typing {
+                    return;
+                }
+
                 if event.physical_key == KeyCode::Escape {
+                    stop_recording(&mut self.recording, "Recording stopped");
                     event_loop.exit();
                 }
 
@@ -172,16 +383,79 @@ impl ApplicationHandler for App<'_> {
                     return;
                 }
 
-                let key_num = KEYMAP.iter().position(|&kc| kc == event.physical_key);
-                if key_num.is_none() {
+                if event.physical_key == DEBUGGER_KEY && event.state == ElementState::Pressed {
+                    let current = { self.settings.read().unwrap().show_debugger };
+
+                    self.settings.write().unwrap().show_debugger = !current;
+                    return;
+                }
+
+                if event.physical_key == REWIND_KEY {
+                    self.rewinding = event.state == ElementState::Pressed;
+                    return;
+                }
+
+                if event.state == ElementState::Pressed {
+                    match event.physical_key {
+                        key if key == PAUSE_KEY => self.debugger.toggle_pause(),
+                        key if key == BREAKPOINT_KEY => {
+                            self.debugger.toggle_breakpoint(self.vm.pc())
+                        }
+                        key if key == STEP_OVER_KEY => self.debugger.step_over(&self.vm),
+                        key if key == STEP_INTO_KEY && self.modifiers.shift_key() => {
+                            self.debugger.step_out(&self.vm)
+                        }
+                        key if key == STEP_INTO_KEY => self.debugger.step_into(),
+                        _ => {}
+                    }
+                }
+
+                if let Some(slot) = SLOT_KEYS.iter().position(|&kc| kc == event.physical_key) {
+                    if event.state == ElementState::Pressed && !event.repeat {
+                        self.handle_slot(slot as u8 + 1);
+                    }
+                    return;
+                }
+
+                let keymap = self.settings.read().unwrap().keymap;
+                let key_num = keymap.iter().position(|&kc| kc == event.physical_key);
+                if key_num.is_none() || self.player.is_some() {
                     return;
                 }
 
-                self.vm
-                    .set_kb(key_num.unwrap(), event.state == ElementState::Pressed);
+                let pressed = event.state == ElementState::Pressed;
+                self.vm.set_kb(key_num.unwrap(), pressed);
+                if let Some((recorder, _)) = &mut self.recording {
+                    recorder.key(key_num.unwrap(), pressed);
+                }
             }
 
             _ => {}
         }
     }
 }
+
+// Saves what was recorded so far. The borrow of the window in RedrawRequested
+// keeps these from being methods.
+fn stop_recording(recording: &mut Option<(Recorder, PathBuf)>, reason: &str) {
+    let Some((recorder, path)) = recording.take() else {
+        return;
+    };
+
+    let movie = recorder.finish();
+    match std::fs::write(&path, movie.to_string()) {
+        Ok(()) => println!(
+            "{}, saved {} frames to {}",
+            reason,
+            movie.frames,
+            path.display()
+        ),
+        Err(err) => eprintln!("Failed to save movie {}: {}", path.display(), err),
+    }
+}
+
+fn stop_replay(player: &mut Option<Player>, reason: &str) {
+    if player.take().is_some() {
+        println!("{}", reason);
+    }
+}
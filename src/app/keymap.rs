@@ -18,3 +18,61 @@ pub const KEYMAP: [KeyCode; 16] = [
     KeyCode::KeyF,   // E
     KeyCode::KeyV,   // F
 ];
+
+// F1-F8 load a save state slot, with shift held they save it
+pub const SLOT_KEYS: [KeyCode; 8] = [
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+];
+
+// held to play the game backwards
+pub const REWIND_KEY: KeyCode = KeyCode::Backspace;
+
+// debugger, shift + STEP_INTO_KEY steps out
+pub const BREAKPOINT_KEY: KeyCode = KeyCode::F9;
+pub const STEP_OVER_KEY: KeyCode = KeyCode::F10;
+pub const STEP_INTO_KEY: KeyCode = KeyCode::F11;
+pub const PAUSE_KEY: KeyCode = KeyCode::F12;
+
+// shows the debugger windows
+pub const DEBUGGER_KEY: KeyCode = KeyCode::Backquote;
+
+// Keys by their winit names, for keymaps in settings files. The keys above
+// that aren't CHIP-8 keys are left out.
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        &[$((stringify!($key), KeyCode::$key)),*]
+    };
+}
+
+#[rustfmt::skip]
+const KEY_NAMES: &[(&str, KeyCode)] = key_names![
+    KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM,
+    KeyN, KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+    Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8,
+    Numpad9, NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal,
+    NumpadEnter, ArrowUp, ArrowDown, ArrowLeft, ArrowRight, Space, Enter, Tab,
+    ShiftLeft, ShiftRight, ControlLeft, ControlRight, AltLeft, AltRight, Comma,
+    Period, Slash, Minus, Equal, BracketLeft, BracketRight, Quote, Backslash,
+];
+
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    KEY_NAMES
+        .iter()
+        .find(|(_, code)| *code == key)
+        .map(|(name, _)| *name)
+}
+
+pub fn parse_key(name: &str) -> Option<KeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|(_, code)| *code)
+}
@@ -0,0 +1,57 @@
+// Window decoration that only some platforms have. Everything else about the
+// window, transparency and decorations included, goes through plain winit.
+use winit::window::{Window, WindowAttributes};
+
+// whether `set_has_shadow` does anything, the settings hide the checkbox if not
+pub const HAS_SHADOW: bool = cfg!(target_os = "macos");
+
+// macOS: the title bar is transparent and the content runs under it
+#[cfg(target_os = "macos")]
+pub fn window_attributes(attrs: WindowAttributes) -> WindowAttributes {
+    use winit::platform::macos::WindowAttributesExtMacOS;
+
+    attrs
+        .with_titlebar_transparent(true)
+        .with_fullsize_content_view(true)
+        .with_title_hidden(true)
+        .with_has_shadow(true)
+}
+
+// X11 and Wayland: a WM_CLASS and app id for window rules, a compositor
+// decides about transparency and shadows
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub fn window_attributes(attrs: WindowAttributes) -> WindowAttributes {
+    use winit::platform::wayland::WindowAttributesExtWayland;
+    use winit::platform::x11::WindowAttributesExtX11;
+
+    let attrs = WindowAttributesExtX11::with_name(attrs, "chip8", "chip8");
+    WindowAttributesExtWayland::with_name(attrs, "chip8", "chip8")
+}
+
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+pub fn window_attributes(attrs: WindowAttributes) -> WindowAttributes {
+    attrs
+}
+
+#[cfg(target_os = "macos")]
+pub fn set_has_shadow(window: &Window, shadow: bool) {
+    use winit::platform::macos::WindowExtMacOS;
+
+    window.set_has_shadow(shadow);
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn set_has_shadow(_window: &Window, _shadow: bool) {}
@@ -0,0 +1,33 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::vm::Vm;
+
+pub const SLOTS: u8 = 8;
+
+// States live next to the ROM and are named after its hash, so renaming the ROM keeps them.
+pub fn slot_path(vm: &Vm, slot: u8) -> Option<PathBuf> {
+    let rom_path = Path::new(vm.rom_path()?);
+    let hash = vm.rom_hash()?;
+    let dir = rom_path.parent().unwrap_or(Path::new("."));
+
+    Some(dir.join(format!("{}.{}.c8state", hash, slot)))
+}
+
+pub fn save(vm: &Vm, slot: u8) -> io::Result<PathBuf> {
+    let path = slot_path(vm, slot).ok_or_else(no_rom)?;
+    std::fs::write(&path, vm.save_state())?;
+    Ok(path)
+}
+
+pub fn load(vm: &mut Vm, slot: u8) -> io::Result<PathBuf> {
+    let path = slot_path(vm, slot).ok_or_else(no_rom)?;
+    let data = std::fs::read(&path)?;
+    vm.load_state(&data)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(path)
+}
+
+fn no_rom() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no ROM loaded")
+}
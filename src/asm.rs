@@ -0,0 +1,771 @@
+mod lexer;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::vm::MEM_SIZE;
+use crate::vm::instruction::Instruction;
+use lexer::{Tok, Token};
+
+const ROM_START: usize = 0x200;
+const MAX_INCLUDE_DEPTH: usize = 16;
+// how deep constants may refer to other constants
+const MAX_CONSTANT_DEPTH: usize = 32;
+
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW",
+    "SKP", "SKNP", "PLANE", "DB", "DW",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, self.line, self.col, self.message
+            )
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug, Clone)]
+struct Loc {
+    file: String,
+    line: usize,
+    col: usize,
+}
+
+impl Loc {
+    fn at(&self, col: usize) -> Loc {
+        Loc {
+            col,
+            ..self.clone()
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            col: self.col,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Num(i64),
+    Sym(String),
+}
+
+// a sum of numbers and symbols, symbols are resolved once every label is known
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(bool, Term, Loc)>,
+    loc: Loc,
+}
+
+impl Expr {
+    fn num(val: i64, loc: Loc) -> Self {
+        Self {
+            terms: vec![(false, Term::Num(val), loc.clone())],
+            loc,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(u8),
+    I,
+    // [I]
+    IndI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    // `long expr`, the XO-CHIP 16-bit address of F000 nnnn
+    Long(Expr),
+    // `Vx - Vy` of SAVE and LOAD
+    RegRange(u8, u8),
+    Expr(Expr),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Ins {
+        mnemonic: String,
+        operands: Vec<Operand>,
+        loc: Loc,
+    },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    pub constants: BTreeMap<String, i64>,
+}
+
+impl Program {
+    // `name = 0x0200` lines, labels by address then constants by name. Constants
+    // aren't always addresses and can be negative, so they're decimal.
+    pub fn symbol_map(&self) -> String {
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, addr)| (**addr, (*name).clone()));
+
+        let mut out = String::new();
+        for (name, addr) in labels {
+            out.push_str(&format!("{} = 0x{:04x}\n", name, addr));
+        }
+        for (name, val) in &self.constants {
+            out.push_str(&format!("{} = {}\n", name, val));
+        }
+
+        out
+    }
+}
+
+// Assembles Cowgod-style source into a ROM that starts at 0x200.
+//
+//   SPRITE_H = 5            ; constants, `equ` works too
+//   start:
+//       LD I, digit
+//       DRW V0, V1, SPRITE_H
+//       JP start
+//   digit:
+//       db 0xf0, 0x90, 0x90, 0x90, 0xf0
+//
+// Also `dw` words, `db "text"`, `include "other.asm"` relative to the including
+// file and `LD I, long addr` for XO-CHIP.
+pub fn assemble_file(path: &Path) -> Result<Program, AsmError> {
+    let source = std::fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        col: 0,
+        message: err.to_string(),
+    })?;
+
+    let mut asm = Assembler::default();
+    asm.load(path, &source)?;
+    asm.finish()
+}
+
+// includes are resolved relative to the working directory
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut asm = Assembler::default();
+    asm.load(Path::new("<source>"), source)?;
+    asm.finish()
+}
+
+#[derive(Debug, Default)]
+struct Assembler {
+    stmts: Vec<Stmt>,
+    addr: usize,
+    labels: HashMap<String, (u16, Loc)>,
+    constants: HashMap<String, (Expr, Loc)>,
+    includes: Vec<PathBuf>,
+}
+
+impl Assembler {
+    // first pass, collects statements and assigns every label its address
+    fn load(&mut self, path: &Path, source: &str) -> Result<(), AsmError> {
+        if self.addr == 0 {
+            self.addr = ROM_START;
+        }
+
+        let file = path.display().to_string();
+        self.includes.push(path.to_path_buf());
+
+        for (idx, line) in source.lines().enumerate() {
+            let loc = Loc {
+                file: file.clone(),
+                line: idx + 1,
+                col: 1,
+            };
+
+            let tokens = lexer::tokenize(line).map_err(|(col, msg)| loc.at(col).error(msg))?;
+            self.statement(&loc, &tokens)?;
+        }
+
+        self.includes.pop();
+        Ok(())
+    }
+
+    fn statement(&mut self, loc: &Loc, mut tokens: &[Token]) -> Result<(), AsmError> {
+        // any number of `label:` in front of the statement
+        while tokens.len() >= 2 && tokens[1].is_punct(':') {
+            let Some(name) = tokens[0].ident() else {
+                return Err(loc.at(tokens[0].col).error("expected a label name"));
+            };
+
+            self.define(name, loc.at(tokens[0].col))?;
+            // after a program that fills memory, there's no address left for it
+            if self.addr >= MEM_SIZE {
+                return Err(loc
+                    .at(tokens[0].col)
+                    .error("label is past the end of memory"));
+            }
+            self.labels
+                .insert(name.to_string(), (self.addr as u16, loc.at(tokens[0].col)));
+            tokens = &tokens[2..];
+        }
+
+        let Some(first) = tokens.first() else {
+            return Ok(());
+        };
+        let loc = loc.at(first.col);
+        let Some(word) = first.ident() else {
+            return Err(loc.error("expected a label, directive or instruction"));
+        };
+
+        let is_constant = tokens.get(1).is_some_and(|tok| {
+            tok.is_punct('=') || tok.ident().is_some_and(|w| w.eq_ignore_ascii_case("equ"))
+        });
+        if is_constant {
+            self.define(word, loc.clone())?;
+            let expr = parse_expr(&loc, &tokens[2..], tokens[1].col)?;
+            self.constants.insert(word.to_string(), (expr, loc));
+            return Ok(());
+        }
+
+        match word.to_ascii_lowercase().as_str() {
+            "db" | "byte" => {
+                let exprs = parse_data(&loc, &tokens[1..])?;
+                self.addr += exprs.len();
+                self.stmts.push(Stmt::Bytes(exprs));
+            }
+            "dw" | "word" => {
+                let exprs = parse_data(&loc, &tokens[1..])?;
+                self.addr += exprs.len() * 2;
+                self.stmts.push(Stmt::Words(exprs));
+            }
+            "include" => self.include(&loc, &tokens[1..])?,
+            _ => {
+                let operands = parse_operands(&loc, &tokens[1..])?;
+                let is_long = word.eq_ignore_ascii_case("LD")
+                    && matches!(operands[..], [Operand::I, Operand::Long(_)]);
+
+                self.addr += if is_long { 4 } else { 2 };
+                self.stmts.push(Stmt::Ins {
+                    mnemonic: word.to_string(),
+                    operands,
+                    loc: loc.clone(),
+                });
+            }
+        }
+
+        if self.addr > MEM_SIZE {
+            return Err(loc.error("program doesn't fit in memory"));
+        }
+
+        Ok(())
+    }
+
+    fn define(&self, name: &str, loc: Loc) -> Result<(), AsmError> {
+        if parse_special(name).is_some() || MNEMONICS.contains(&name.to_ascii_uppercase().as_str())
+        {
+            return Err(loc.error(format!("`{}` is a reserved name", name)));
+        }
+
+        let previous = self
+            .labels
+            .get(name)
+            .map(|(_, loc)| loc)
+            .or_else(|| self.constants.get(name).map(|(_, loc)| loc));
+        if let Some(previous) = previous {
+            return Err(loc.error(format!("`{}` is already defined at {}", name, previous)));
+        }
+
+        Ok(())
+    }
+
+    fn include(&mut self, loc: &Loc, tokens: &[Token]) -> Result<(), AsmError> {
+        let [
+            Token {
+                tok: Tok::Str(path),
+                ..
+            },
+        ] = tokens
+        else {
+            return Err(loc.error("expected `include \"file\"`"));
+        };
+
+        let path = String::from_utf8_lossy(path).to_string();
+        let base = self
+            .includes
+            .last()
+            .and_then(|file| file.parent())
+            .unwrap_or(Path::new(""));
+        let path = base.join(path);
+
+        if self.includes.len() >= MAX_INCLUDE_DEPTH || self.includes.contains(&path) {
+            return Err(loc.error(format!("recursive include of {}", path.display())));
+        }
+
+        let source = std::fs::read_to_string(&path)
+            .map_err(|err| loc.error(format!("can't read {}: {}", path.display(), err)))?;
+        self.load(&path, &source)
+    }
+
+    // second pass, everything is known now
+    fn finish(self) -> Result<Program, AsmError> {
+        let mut rom = Vec::with_capacity(self.addr - ROM_START);
+
+        for stmt in &self.stmts {
+            match stmt {
+                Stmt::Bytes(exprs) => {
+                    for expr in exprs {
+                        rom.push(self.value_in(expr, -0x80, 0xff)? as u8);
+                    }
+                }
+                Stmt::Words(exprs) => {
+                    for expr in exprs {
+                        let val = self.value_in(expr, -0x8000, 0xffff)? as u16;
+                        rom.extend(val.to_be_bytes());
+                    }
+                }
+                Stmt::Ins {
+                    mnemonic,
+                    operands,
+                    loc,
+                } => {
+                    let (ins, long) = self.encode(mnemonic, operands, loc)?;
+                    rom.extend(ins.encode().to_be_bytes());
+                    if let Some(long) = long {
+                        rom.extend(long.to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        let mut constants = BTreeMap::new();
+        for (name, (expr, _)) in &self.constants {
+            constants.insert(name.clone(), self.value(expr, 0)?);
+        }
+
+        Ok(Program {
+            rom,
+            labels: self
+                .labels
+                .into_iter()
+                .map(|(name, (addr, _))| (name, addr))
+                .collect(),
+            constants,
+        })
+    }
+
+    fn value(&self, expr: &Expr, depth: usize) -> Result<i64, AsmError> {
+        let mut sum: i64 = 0;
+
+        for (negate, term, loc) in &expr.terms {
+            let val = match term {
+                Term::Num(val) => *val,
+                Term::Sym(name) => {
+                    if let Some((addr, _)) = self.labels.get(name) {
+                        *addr as i64
+                    } else if let Some((constant, _)) = self.constants.get(name) {
+                        if depth >= MAX_CONSTANT_DEPTH {
+                            return Err(
+                                loc.error(format!("`{}` is defined in terms of itself", name))
+                            );
+                        }
+                        self.value(constant, depth + 1)?
+                    } else {
+                        return Err(loc.error(format!("undefined symbol `{}`", name)));
+                    }
+                }
+            };
+
+            sum = if *negate {
+                sum.wrapping_sub(val)
+            } else {
+                sum.wrapping_add(val)
+            };
+        }
+
+        Ok(sum)
+    }
+
+    fn value_in(&self, expr: &Expr, min: i64, max: i64) -> Result<i64, AsmError> {
+        let val = self.value(expr, 0)?;
+        if val < min || val > max {
+            return Err(expr
+                .loc
+                .error(format!("value {} is out of range {}..={}", val, min, max)));
+        }
+
+        Ok(val)
+    }
+
+    fn encode(
+        &self,
+        mnemonic: &str,
+        operands: &[Operand],
+        loc: &Loc,
+    ) -> Result<(Instruction, Option<u16>), AsmError> {
+        use Instruction::*;
+        use Operand::{B, Dt, F, Hf, I, IndI, K, Long, R, Reg, RegRange, St};
+
+        let nibble = |expr| self.value_in(expr, 0, 0x0f).map(|val| val as u8);
+        let byte = |expr| self.value_in(expr, -0x80, 0xff).map(|val| val as u8);
+        let addr = |expr| self.value_in(expr, 0, 0x0fff).map(|val| val as u16);
+
+        let upper = mnemonic.to_ascii_uppercase();
+        let mut long = None;
+
+        let ins = match (upper.as_str(), operands) {
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("SCR", []) => Scr,
+            ("SCL", []) => Scl,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Low,
+            ("HIGH", []) => High,
+            ("SCD", [Operand::Expr(n)]) => Scd(nibble(n)?),
+            ("SCU", [Operand::Expr(n)]) => Scu(nibble(n)?),
+            ("JP", [Operand::Expr(target)]) => Jp(addr(target)?),
+            ("JP", [Reg(0), Operand::Expr(target)]) => {
+                let target = addr(target)?;
+                JpV0 {
+                    x: (target >> 8) as u8,
+                    addr: target,
+                }
+            }
+            ("CALL", [Operand::Expr(target)]) => Call(addr(target)?),
+            ("SE", [Reg(x), Reg(y)]) => SeReg { x: *x, y: *y },
+            ("SE", [Reg(x), Operand::Expr(kk)]) => SeImm {
+                x: *x,
+                kk: byte(kk)?,
+            },
+            ("SNE", [Reg(x), Reg(y)]) => SneReg { x: *x, y: *y },
+            ("SNE", [Reg(x), Operand::Expr(kk)]) => SneImm {
+                x: *x,
+                kk: byte(kk)?,
+            },
+            ("SAVE", [RegRange(x, y)]) => Save { x: *x, y: *y },
+            ("LOAD", [RegRange(x, y)]) => Load { x: *x, y: *y },
+            ("LD", [Reg(x), Reg(y)]) => LdReg { x: *x, y: *y },
+            ("LD", [Reg(x), Operand::Expr(kk)]) => LdImm {
+                x: *x,
+                kk: byte(kk)?,
+            },
+            ("LD", [I, Operand::Expr(target)]) => LdI(addr(target)?),
+            ("LD", [I, Long(target)]) => {
+                long = Some(self.value_in(target, 0, 0xffff)? as u16);
+                LdILong
+            }
+            ("LD", [Reg(x), Dt]) => LdVxDt(*x),
+            ("LD", [Reg(x), K]) => LdVxK(*x),
+            ("LD", [Dt, Reg(x)]) => LdDtVx(*x),
+            ("LD", [St, Reg(x)]) => LdStVx(*x),
+            ("LD", [F, Reg(x)]) => LdF(*x),
+            ("LD", [Hf, Reg(x)]) => LdHf(*x),
+            ("LD", [B, Reg(x)]) => LdB(*x),
+            ("LD", [IndI, Reg(x)]) => LdIVx(*x),
+            ("LD", [Reg(x), IndI]) => LdVxI(*x),
+            ("LD", [R, Reg(x)]) => LdRVx(*x),
+            ("LD", [Reg(x), R]) => LdVxR(*x),
+            ("ADD", [Reg(x), Reg(y)]) => AddReg { x: *x, y: *y },
+            ("ADD", [Reg(x), Operand::Expr(kk)]) => AddImm {
+                x: *x,
+                kk: byte(kk)?,
+            },
+            ("ADD", [I, Reg(x)]) => AddI(*x),
+            ("OR", [Reg(x), Reg(y)]) => Or { x: *x, y: *y },
+            ("AND", [Reg(x), Reg(y)]) => And { x: *x, y: *y },
+            ("XOR", [Reg(x), Reg(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [Reg(x), Reg(y)]) => Sub { x: *x, y: *y },
+            ("SUBN", [Reg(x), Reg(y)]) => Subn { x: *x, y: *y },
+            // without Vy the shift quirk shifts Vx either way
+            ("SHR", [Reg(x)]) => Shr { x: *x, y: *x },
+            ("SHR", [Reg(x), Reg(y)]) => Shr { x: *x, y: *y },
+            ("SHL", [Reg(x)]) => Shl { x: *x, y: *x },
+            ("SHL", [Reg(x), Reg(y)]) => Shl { x: *x, y: *y },
+            ("RND", [Reg(x), Operand::Expr(kk)]) => Rnd {
+                x: *x,
+                kk: byte(kk)?,
+            },
+            ("DRW", [Reg(x), Reg(y), Operand::Expr(n)]) => Drw {
+                x: *x,
+                y: *y,
+                n: nibble(n)?,
+            },
+            ("SKP", [Reg(x)]) => Skp(*x),
+            ("SKNP", [Reg(x)]) => Sknp(*x),
+            ("PLANE", [Operand::Expr(n)]) => Plane(self.value_in(n, 0, 3)? as u8),
+            (upper, _) if MNEMONICS.contains(&upper) => {
+                return Err(loc.error(format!("invalid operands for `{}`", mnemonic)));
+            }
+            _ => return Err(loc.error(format!("unknown instruction `{}`", mnemonic))),
+        };
+
+        Ok((ins, long))
+    }
+}
+
+// V0-VF
+fn parse_reg(name: &str) -> Option<u8> {
+    let idx = name.strip_prefix(['V', 'v'])?;
+    if idx.len() != 1 {
+        return None;
+    }
+
+    u8::from_str_radix(idx, 16).ok()
+}
+
+fn parse_special(name: &str) -> Option<Operand> {
+    if let Some(reg) = parse_reg(name) {
+        return Some(Operand::Reg(reg));
+    }
+
+    let operand = match name.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        _ => return None,
+    };
+
+    Some(operand)
+}
+
+// comma separated groups, `col` of each group's first token
+fn split_commas<'t>(loc: &Loc, tokens: &'t [Token]) -> Result<Vec<&'t [Token]>, AsmError> {
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let groups: Vec<&[Token]> = tokens.split(|tok| tok.is_punct(',')).collect();
+    for (idx, group) in groups.iter().enumerate() {
+        if group.is_empty() {
+            let col = tokens
+                .iter()
+                .filter(|tok| tok.is_punct(','))
+                .nth(idx.saturating_sub(1))
+                .map_or(loc.col, |tok| tok.col);
+            return Err(loc.at(col).error("missing operand"));
+        }
+    }
+
+    Ok(groups)
+}
+
+fn parse_operands(loc: &Loc, tokens: &[Token]) -> Result<Vec<Operand>, AsmError> {
+    let mut operands = Vec::new();
+
+    for group in split_commas(loc, tokens)? {
+        let special = group
+            .first()
+            .and_then(|tok| tok.ident())
+            .and_then(parse_special);
+
+        let operand = match (group, special) {
+            ([_], Some(special)) => special,
+            ([open, i, close], _) if open.is_punct('[') && close.is_punct(']') => {
+                match i.ident().and_then(parse_special) {
+                    Some(Operand::I) => Operand::IndI,
+                    _ => return Err(loc.at(i.col).error("expected `[I]`")),
+                }
+            }
+            ([_, dash, last], Some(Operand::Reg(x))) if dash.is_punct('-') => {
+                match last.ident().and_then(parse_reg) {
+                    Some(y) => Operand::RegRange(x, y),
+                    None => return Err(loc.at(last.col).error("expected a register")),
+                }
+            }
+            ([first, rest @ ..], _)
+                if first
+                    .ident()
+                    .is_some_and(|w| w.eq_ignore_ascii_case("long")) =>
+            {
+                Operand::Long(parse_expr(loc, rest, first.col)?)
+            }
+            (_, Some(_)) => {
+                return Err(loc
+                    .at(group[0].col)
+                    .error("unexpected tokens after register"));
+            }
+            _ => Operand::Expr(parse_expr(loc, group, group[0].col)?),
+        };
+
+        operands.push(operand);
+    }
+
+    Ok(operands)
+}
+
+// bytes of strings are expanded into one expression each
+fn parse_data(loc: &Loc, tokens: &[Token]) -> Result<Vec<Expr>, AsmError> {
+    let mut exprs = Vec::new();
+
+    for group in split_commas(loc, tokens)? {
+        match group {
+            [
+                Token {
+                    tok: Tok::Str(bytes),
+                    col,
+                },
+            ] => {
+                exprs.extend(bytes.iter().map(|&b| Expr::num(b as i64, loc.at(*col))));
+            }
+            _ => exprs.push(parse_expr(loc, group, group[0].col)?),
+        }
+    }
+
+    if exprs.is_empty() {
+        return Err(loc.error("expected at least one value"));
+    }
+
+    Ok(exprs)
+}
+
+// [-] term {(+|-) term}, a term being a number or a symbol
+fn parse_expr(loc: &Loc, tokens: &[Token], col: usize) -> Result<Expr, AsmError> {
+    let mut terms = Vec::new();
+    let mut negate = false;
+    let mut expect_term = true;
+
+    for tok in tokens {
+        let tok_loc = loc.at(tok.col);
+
+        if expect_term {
+            if terms.is_empty() && tok.is_punct('-') && !negate {
+                negate = true;
+                continue;
+            }
+
+            let term = match &tok.tok {
+                Tok::Num(val) => Term::Num(*val),
+                Tok::Ident(name) if parse_special(name).is_some() => {
+                    return Err(tok_loc.error(format!("`{}` can't be used in an expression", name)));
+                }
+                Tok::Ident(name) => Term::Sym(name.clone()),
+                _ => return Err(tok_loc.error("expected a number or symbol")),
+            };
+
+            terms.push((negate, term, tok_loc));
+            expect_term = false;
+        } else {
+            negate = if tok.is_punct('+') {
+                false
+            } else if tok.is_punct('-') {
+                true
+            } else {
+                return Err(tok_loc.error("expected `+`, `-` or `,`"));
+            };
+            expect_term = true;
+        }
+    }
+
+    if expect_term {
+        let col = tokens.last().map_or(col, |tok| tok.col + 1);
+        return Err(loc.at(col).error("expected a value"));
+    }
+
+    Ok(Expr {
+        terms,
+        loc: loc.at(col),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble(
+            "
+            HEIGHT equ 5
+            BACK = -20
+            start:  LD I, digit     ; comment
+                    LD V0, 0x10
+                    DRW V0, V1, HEIGHT
+                    SAVE V0 - V3
+                    JP start
+            digit:  db 0xf0, 'A', \"hi\"
+                    dw digit + 1
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.rom,
+            [
+                0xa2, 0x0a, 0x60, 0x10, 0xd0, 0x15, 0x50, 0x32, 0x12, 0x00, 0xf0, 0x41, 0x68, 0x69,
+                0x02, 0x0b,
+            ]
+        );
+        assert_eq!(program.labels["digit"], 0x20a);
+        assert!(
+            program
+                .symbol_map()
+                .starts_with("start = 0x0200\ndigit = 0x020a\n")
+        );
+        assert!(program.symbol_map().ends_with("BACK = -20\nHEIGHT = 5\n"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = assemble("  LD V0, 0x100").unwrap_err();
+        assert_eq!((err.line, err.col), (1, 10));
+
+        let err = assemble("\nJP nowhere").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 4));
+        assert!(err.message.contains("nowhere"));
+
+        let err = assemble("a:\na:").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 1));
+
+        let err = assemble("  MOV V0, V1").unwrap_err();
+        assert_eq!((err.line, err.col), (1, 3));
+
+        let full = format!("db {}\n  end:", vec!["0"; MEM_SIZE - 0x200].join(", "));
+        let err = assemble(&full).unwrap_err();
+        assert_eq!((err.line, err.col), (2, 3));
+    }
+
+    #[test]
+    fn test_disasm_roundtrip() {
+        let rom = [
+            0x00, 0xe0, 0xa2, 0x10, 0x22, 0x0c, 0xf0, 0x00, 0x02, 0x10, 0x12, 0x0a, 0xd0, 0x1f,
+            0x00, 0xee, 0x3c, 0x42,
+        ];
+
+        let source = disasm::disassemble(&rom);
+        assert_eq!(assemble(&source).unwrap().rom, rom);
+    }
+}
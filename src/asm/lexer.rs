@@ -0,0 +1,165 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tok {
+    Ident(String),
+    Num(i64),
+    Str(Vec<u8>),
+    Punct(char),
+}
+
+// cols are 1-based, like the line numbers in errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub tok: Tok,
+    pub col: usize,
+}
+
+impl Token {
+    pub fn ident(&self) -> Option<&str> {
+        match &self.tok {
+            Tok::Ident(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn is_punct(&self, ch: char) -> bool {
+        self.tok == Tok::Punct(ch)
+    }
+}
+
+// Splits one source line into tokens, everything after `;` is a comment.
+pub fn tokenize(line: &str) -> Result<Vec<Token>, (usize, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let ch = chars[pos];
+        let col = pos + 1;
+
+        if ch.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        if ch == ';' {
+            break;
+        }
+
+        if ch.is_ascii_alphabetic() || ch == '_' || ch == '.' {
+            let start = pos;
+            while pos < chars.len()
+                && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '_' || chars[pos] == '.')
+            {
+                pos += 1;
+            }
+
+            let name: String = chars[start..pos].iter().collect();
+            tokens.push(Token {
+                tok: Tok::Ident(name),
+                col,
+            });
+            continue;
+        }
+
+        if ch.is_ascii_digit() || ch == '$' || ch == '%' {
+            let start = pos;
+            pos += 1;
+            while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+
+            let text: String = chars[start..pos].iter().collect();
+            let val = parse_number(&text).ok_or((col, format!("invalid number `{}`", text)))?;
+            tokens.push(Token {
+                tok: Tok::Num(val),
+                col,
+            });
+            continue;
+        }
+
+        if ch == '"' {
+            let (bytes, end) = parse_string(&chars, pos)?;
+            tokens.push(Token {
+                tok: Tok::Str(bytes),
+                col,
+            });
+            pos = end;
+            continue;
+        }
+
+        if ch == '\'' {
+            let (bytes, end) = parse_string(&chars, pos)?;
+            let [byte] = bytes[..] else {
+                return Err((col, "character literal must be one character".to_string()));
+            };
+
+            tokens.push(Token {
+                tok: Tok::Num(byte as i64),
+                col,
+            });
+            pos = end;
+            continue;
+        }
+
+        if ",:[]+-=()".contains(ch) {
+            tokens.push(Token {
+                tok: Tok::Punct(ch),
+                col,
+            });
+            pos += 1;
+            continue;
+        }
+
+        return Err((col, format!("unexpected character `{}`", ch)));
+    }
+
+    Ok(tokens)
+}
+
+// decimal, `0x`/`$` hex and `0b`/`%` binary, `_` separators are allowed
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.replace('_', "");
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+// `"..."` or `'.'` starting at `start`, returns the bytes and the position after the quote
+fn parse_string(chars: &[char], start: usize) -> Result<(Vec<u8>, usize), (usize, String)> {
+    let quote = chars[start];
+    let mut bytes = Vec::new();
+    let mut pos = start + 1;
+
+    loop {
+        let Some(&ch) = chars.get(pos) else {
+            return Err((start + 1, "unterminated string".to_string()));
+        };
+        pos += 1;
+
+        let ch = match ch {
+            ch if ch == quote => return Ok((bytes, pos)),
+            '\\' => {
+                let escaped = chars.get(pos).copied();
+                pos += 1;
+                match escaped {
+                    Some('n') => '\n',
+                    Some('0') => '\0',
+                    Some(ch @ ('\\' | '"' | '\'')) => ch,
+                    _ => return Err((pos - 1, "invalid escape".to_string())),
+                }
+            }
+            ch => ch,
+        };
+
+        if !ch.is_ascii() {
+            return Err((pos - 1, format!("`{}` is not ASCII", ch)));
+        }
+        bytes.push(ch as u8);
+    }
+}
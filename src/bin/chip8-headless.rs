@@ -0,0 +1,167 @@
+use clap::Parser;
+
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use chip8::headless::{self, Runner};
+use chip8::movie::Movie;
+use chip8::octo;
+use chip8::settings::Settings;
+use chip8::trace::TraceArgs;
+use chip8::vm::{QuirksPreset, Vm};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Run a CHIP-8 ROM without a window")]
+struct Args {
+    #[arg(index = 1, help = "ROM, or Octo source (.8o) to compile")]
+    rom_path: String,
+
+    #[arg(long, help = "Frames to run [default: 60, or the length of the movie]")]
+    frames: Option<u64>,
+
+    #[arg(
+        long,
+        conflicts_with = "frames",
+        help = "Instructions to run instead of frames"
+    )]
+    cycles: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Instructions per 60Hz frame [default: from --config or the ROM database, else 10]"
+    )]
+    ticks_per_frame: Option<u32>,
+
+    #[arg(long, value_enum, help = "Quirks preset")]
+    quirks: Option<QuirksPreset>,
+
+    #[arg(
+        long,
+        help = "Seed for RND (Cxkk), runs with the same seed and input are identical [default: random]"
+    )]
+    seed: Option<u64>,
+
+    #[arg(long, help = "Input script, lines of `<frame> <key> down|up`")]
+    input: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["input", "quirks", "seed", "ticks_per_frame"],
+        help = "Play back a movie recorded with `chip8 --record`"
+    )]
+    replay: Option<PathBuf>,
+
+    #[arg(long, help = "Write the screen as text, `-` for stdout")]
+    screen: Option<String>,
+
+    #[arg(long, help = "Write the screen as PNG")]
+    png: Option<PathBuf>,
+
+    #[arg(long, help = "Write registers as JSON, `-` for stdout")]
+    registers: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Settings file to take the speed, quirks and colours from, and the ROM profiles next to it [default: none, runs are the same on every machine]"
+    )]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    trace: TraceArgs,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    // the defaults unless a config is given, not whatever this machine has saved
+    let mut settings = Settings::load(args.config.clone());
+
+    let mut vm = Vm::new();
+    if let Some(seed) = args.seed {
+        vm.set_seed(seed);
+    }
+
+    if octo::is_octo_source(Path::new(&args.rom_path)) {
+        let program = match octo::compile_file(Path::new(&args.rom_path)) {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        vm.load_rom_bytes(args.rom_path.clone(), &program.rom)?;
+    } else {
+        vm.load_rom(args.rom_path.clone())?;
+    }
+    settings.load_rom(&vm);
+    vm.set_quirks(
+        args.quirks
+            .map_or(settings.quirks, |preset| preset.quirks()),
+    );
+
+    let mut events = match &args.input {
+        Some(path) => headless::parse_script(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    let mut ticks_per_frame = args.ticks_per_frame.unwrap_or(settings.ticks_per_frame);
+    let mut frames = args.frames.unwrap_or(60);
+
+    if let Some(path) = &args.replay {
+        let movie = Movie::parse(&std::fs::read_to_string(path)?)?;
+        if !movie.matches_rom(&vm) {
+            eprintln!("{}: recorded with another ROM", path.display());
+            std::process::exit(1);
+        }
+
+        vm.set_seed(movie.seed);
+        vm.set_quirks(movie.quirks);
+        ticks_per_frame = movie.ticks_per_frame;
+        frames = args.frames.unwrap_or(movie.frames);
+        events = movie.events;
+    }
+
+    let mut runner = Runner::new(vm, ticks_per_frame, events);
+    runner.tracer = args.trace.tracer()?;
+
+    let result = match args.cycles {
+        Some(cycles) => runner.run_cycles(cycles),
+        None => runner.run_frames(frames),
+    };
+
+    // dump whatever state we ended up in, even if the VM halted
+    let screen = match (&args.screen, &args.png, &args.registers) {
+        (None, None, None) => Some("-".to_string()),
+        _ => args.screen.clone(),
+    };
+
+    if let Some(screen) = screen {
+        write_output(&screen, &headless::screen_text(&runner.vm.vb))?;
+    }
+
+    if let Some(png) = &args.png {
+        headless::write_png(&runner.vm.vb, &settings.palette(), png)?;
+    }
+
+    if let Some(registers) = &args.registers {
+        write_output(registers, &headless::registers_json(&runner))?;
+    }
+
+    if let Some(tracer) = runner.tracer.take() {
+        tracer.finish()?;
+    }
+
+    result?;
+    Ok(())
+}
+
+fn write_output(dest: &str, contents: &str) -> std::io::Result<()> {
+    if dest == "-" {
+        print!("{}", contents);
+        Ok(())
+    } else {
+        std::fs::write(dest, contents)
+    }
+}
@@ -0,0 +1,553 @@
+pub mod expr;
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::vm::instruction::{self, Instruction};
+use crate::vm::{AccessKind, Vm};
+
+pub use expr::Expr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    StepInto,
+    // run until the CALL at `ret - 2` returns
+    StepOver { ret: u16, sp: u16 },
+    // run until the stack drops below `sp`
+    StepOut { sp: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Paused,
+    Breakpoint(u16),
+    Step,
+    Watchpoint {
+        addr: u16,
+        kind: AccessKind,
+        value: u8,
+    },
+    Condition(String),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Paused => write!(f, "paused"),
+            StopReason::Breakpoint(addr) => write!(f, "breakpoint at 0x{:03x}", addr),
+            StopReason::Step => write!(f, "step"),
+            StopReason::Watchpoint { addr, kind, value } => {
+                let verb = match kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                write!(f, "{} of 0x{:02x} at 0x{:03x}", verb, value, addr)
+            }
+            StopReason::Condition(src) => write!(f, "`{}` became true", src),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Any,
+}
+
+impl WatchKind {
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::Any => true,
+        }
+    }
+}
+
+// Inclusive address range that stops the VM when an instruction touches it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u16, kind: AccessKind) -> bool {
+        (self.start..=self.end).contains(&addr) && self.kind.matches(kind)
+    }
+}
+
+// Stops when the expression goes from false to true, not on every tick it holds.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    src: String,
+    expr: Expr,
+    was_true: bool,
+}
+
+impl Condition {
+    pub fn new(src: &str) -> Result<Self, expr::ParseError> {
+        Ok(Self {
+            src: src.to_string(),
+            expr: Expr::parse(src)?,
+            was_true: false,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.src
+    }
+}
+
+// Bytes shown in the Monitors window, Octo's `:monitor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: String,
+    pub addr: u16,
+    pub len: u16,
+}
+
+// App asks the debugger before every tick whether the VM may run.
+#[derive(Debug)]
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    conditions: Vec<Condition>,
+    monitors: Vec<Monitor>,
+    // leaving a pause must not stop again on the breakpoint we are sitting on
+    skip_check: bool,
+    stop: Option<StopReason>,
+    // registers or memory were changed by hand, which a movie can't replay
+    edited: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Running,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new(),
+            monitors: Vec::new(),
+            skip_check: false,
+            stop: None,
+            edited: false,
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        if !self.is_paused() {
+            self.stop_with(StopReason::Paused);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.leave_pause(Mode::Running);
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    pub fn step_into(&mut self) {
+        self.leave_pause(Mode::StepInto);
+    }
+
+    // steps over 2nnn CALLs, behaves like step_into for everything else
+    pub fn step_over(&mut self, vm: &Vm) {
+        let pc = vm.pc();
+        let is_call = vm
+            .mem()
+            .get(pc as usize..pc as usize + 2)
+            .is_some_and(|bytes| {
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+                matches!(instruction::decode(opcode), Instruction::Call(_))
+            });
+
+        if is_call {
+            self.leave_pause(Mode::StepOver {
+                ret: pc.wrapping_add(2),
+                sp: vm.sp(),
+            });
+        } else {
+            self.step_into();
+        }
+    }
+
+    // runs until the current subroutine returns with 00EE
+    pub fn step_out(&mut self, vm: &Vm) {
+        if vm.sp() == 0 {
+            return;
+        }
+
+        self.leave_pause(Mode::StepOut { sp: vm.sp() });
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn toggle_breakpoint(&mut self, addr: u16) {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, idx: usize) {
+        if idx < self.watchpoints.len() {
+            self.watchpoints.remove(idx);
+        }
+    }
+
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+    }
+
+    pub fn remove_condition(&mut self, idx: usize) {
+        if idx < self.conditions.len() {
+            self.conditions.remove(idx);
+        }
+    }
+
+    pub fn monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+
+    pub fn add_monitor(&mut self, monitor: Monitor) {
+        self.monitors.push(monitor);
+    }
+
+    // watchpoints only see anything if the VM records its memory accesses
+    pub fn needs_accesses(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    // why the debugger last stopped, cleared once taken
+    pub fn take_stop(&mut self) -> Option<StopReason> {
+        self.stop.take()
+    }
+
+    pub fn set_edited(&mut self) {
+        self.edited = true;
+    }
+
+    // whether anything was edited since the last call
+    pub fn take_edited(&mut self) -> bool {
+        std::mem::take(&mut self.edited)
+    }
+
+    // false if the next instruction must not be executed
+    pub fn before_tick(&mut self, vm: &Vm) -> bool {
+        if self.mode == Mode::Paused {
+            return false;
+        }
+
+        let skip_check = std::mem::take(&mut self.skip_check);
+        if !skip_check && self.breakpoints.contains(&vm.pc()) {
+            self.stop_with(StopReason::Breakpoint(vm.pc()));
+            return false;
+        }
+
+        true
+    }
+
+    pub fn after_tick(&mut self, vm: &Vm) {
+        let hit = vm.mem_accesses().iter().find(|access| {
+            self.watchpoints
+                .iter()
+                .any(|watch| watch.matches(access.addr, access.kind))
+        });
+
+        if let Some(access) = hit {
+            self.stop_with(StopReason::Watchpoint {
+                addr: access.addr,
+                kind: access.kind,
+                value: access.value,
+            });
+            return;
+        }
+
+        let mut fired = None;
+        for condition in &mut self.conditions {
+            let is_true = condition.expr.is_true(vm);
+            if is_true && !condition.was_true && fired.is_none() {
+                fired = Some(condition.src.clone());
+            }
+            condition.was_true = is_true;
+        }
+
+        if let Some(src) = fired {
+            self.stop_with(StopReason::Condition(src));
+            return;
+        }
+
+        let done = match self.mode {
+            Mode::Running | Mode::Paused => false,
+            Mode::StepInto => true,
+            Mode::StepOver { ret, sp } => vm.pc() == ret && vm.sp() == sp,
+            Mode::StepOut { sp } => vm.sp() < sp,
+        };
+
+        if done {
+            self.stop_with(StopReason::Step);
+        }
+    }
+
+    fn leave_pause(&mut self, mode: Mode) {
+        self.skip_check = self.mode == Mode::Paused;
+        self.mode = mode;
+    }
+
+    fn stop_with(&mut self, reason: StopReason) {
+        self.mode = Mode::Paused;
+        self.stop = Some(reason);
+    }
+}
+
+// accepts `0x2a4`, `$2a4` and bare hex `2a4`
+pub fn parse_addr(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address `{}`", s))
+}
+
+// accepts `start[-end][:r|w|rw]`, e.g. `0x300-0x302:w`, watching reads and writes by default
+pub fn parse_watch(s: &str) -> Result<Watchpoint, String> {
+    let (range, kind) = match s.rsplit_once(':') {
+        Some((range, "r")) => (range, WatchKind::Read),
+        Some((range, "w")) => (range, WatchKind::Write),
+        Some((range, "rw")) => (range, WatchKind::Any),
+        Some((_, kind)) => {
+            return Err(format!(
+                "invalid watch kind `{}`, expected r, w or rw",
+                kind
+            ));
+        }
+        None => (s, WatchKind::Any),
+    };
+
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+        None => {
+            let addr = parse_addr(range)?;
+            (addr, addr)
+        }
+    };
+
+    if end < start {
+        return Err(format!("invalid watch range `{}`", range));
+    }
+
+    Ok(Watchpoint { start, end, kind })
+}
+
+pub fn parse_condition(s: &str) -> Result<Condition, String> {
+    Condition::new(s).map_err(|err| format!("invalid condition `{}`: {}", s, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //   0x200 CALL 0x206
+    //   0x202 LD V0, 1
+    //   0x204 JP 0x204
+    //   0x206 CALL 0x20c
+    //   0x208 LD V1, 2
+    //   0x20a RET
+    //   0x20c LD V2, 3
+    //   0x20e RET
+    const CALLS: [u8; 16] = [
+        0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x22, 0x0c, 0x61, 0x02, 0x00, 0xee, 0x62, 0x03, 0x00,
+        0xee,
+    ];
+
+    // ticks the way App does until the debugger stops or `max` instructions ran
+    fn run(dbg: &mut Debugger, vm: &mut Vm, max: usize) -> usize {
+        vm.set_track_accesses(dbg.needs_accesses());
+        for ticks in 0..max {
+            if !dbg.before_tick(vm) {
+                return ticks;
+            }
+            vm.tick().unwrap();
+            dbg.after_tick(vm);
+        }
+        max
+    }
+
+    fn paused() -> Debugger {
+        let mut dbg = Debugger::new();
+        dbg.pause();
+        assert_eq!(dbg.take_stop(), Some(StopReason::Paused));
+        dbg
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut vm = Vm::from_bytes(&CALLS);
+        let mut dbg = paused();
+        assert_eq!(run(&mut dbg, &mut vm, 100), 0);
+
+        // the whole CALL, nested one included
+        dbg.step_over(&vm);
+        assert_eq!(run(&mut dbg, &mut vm, 100), 6);
+        assert_eq!((vm.pc(), vm.sp()), (0x202, 0));
+        assert_eq!(vm.reg()[2], 3);
+        assert_eq!(dbg.take_stop(), Some(StopReason::Step));
+
+        // anything else is a single step
+        dbg.step_over(&vm);
+        assert_eq!(run(&mut dbg, &mut vm, 100), 1);
+        assert_eq!(vm.pc(), 0x204);
+        assert!(dbg.is_paused());
+    }
+
+    #[test]
+    fn test_step_out() {
+        let mut vm = Vm::from_bytes(&CALLS);
+        let mut dbg = paused();
+
+        // nothing to return from
+        dbg.step_out(&vm);
+        assert_eq!(run(&mut dbg, &mut vm, 100), 0);
+
+        for _ in 0..2 {
+            dbg.step_into();
+            assert_eq!(run(&mut dbg, &mut vm, 100), 1);
+        }
+        assert_eq!((vm.pc(), vm.sp()), (0x20c, 2));
+
+        // stops after the inner RET, not the outer one
+        dbg.step_out(&vm);
+        assert_eq!(run(&mut dbg, &mut vm, 100), 2);
+        assert_eq!((vm.pc(), vm.sp()), (0x208, 1));
+        assert_eq!(dbg.take_stop(), Some(StopReason::Step));
+
+        dbg.step_out(&vm);
+        assert_eq!(run(&mut dbg, &mut vm, 100), 2);
+        assert_eq!((vm.pc(), vm.sp()), (0x202, 0));
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut vm = Vm::from_bytes(&CALLS);
+        let mut dbg = Debugger::new();
+        dbg.add_breakpoint(0x20c);
+
+        // stops before the instruction at the breakpoint
+        assert_eq!(run(&mut dbg, &mut vm, 100), 2);
+        assert_eq!(vm.pc(), 0x20c);
+        assert!(dbg.is_paused());
+        assert_eq!(dbg.take_stop(), Some(StopReason::Breakpoint(0x20c)));
+
+        // resuming runs it instead of stopping there again
+        dbg.resume();
+        assert_eq!(run(&mut dbg, &mut vm, 100), 100);
+        assert_eq!(vm.reg()[2], 3);
+        assert_eq!(dbg.take_stop(), None);
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        // LD I, 0x300; LD V0, 0x42; LD [I], V0; LD V0, [I]; JP 0x208
+        let program = [0xa3, 0x00, 0x60, 0x42, 0xf0, 0x55, 0xf0, 0x65, 0x12, 0x08];
+
+        let mut vm = Vm::from_bytes(&program);
+        let mut dbg = Debugger::new();
+        dbg.add_watchpoint(parse_watch("0x2ff-0x300:w").unwrap());
+        assert_eq!(run(&mut dbg, &mut vm, 100), 3);
+        assert_eq!(vm.pc(), 0x206);
+        let write = StopReason::Watchpoint {
+            addr: 0x300,
+            kind: AccessKind::Write,
+            value: 0x42,
+        };
+        assert_eq!(dbg.take_stop(), Some(write));
+
+        // the write doesn't match a read watch
+        let mut vm = Vm::from_bytes(&program);
+        let mut dbg = Debugger::new();
+        dbg.add_watchpoint(parse_watch("300:r").unwrap());
+        assert_eq!(run(&mut dbg, &mut vm, 100), 4);
+        assert_eq!(vm.pc(), 0x208);
+        assert!(matches!(
+            dbg.take_stop(),
+            Some(StopReason::Watchpoint {
+                kind: AccessKind::Read,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_condition() {
+        // ADD V0, 1; JP 0x200
+        let mut vm = Vm::from_bytes(&[0x70, 0x01, 0x12, 0x00]);
+        let mut dbg = Debugger::new();
+        dbg.add_condition(parse_condition("V0 >= 3").unwrap());
+
+        assert_eq!(run(&mut dbg, &mut vm, 100), 5);
+        assert_eq!(vm.reg()[0], 3);
+        assert_eq!(
+            dbg.take_stop(),
+            Some(StopReason::Condition("V0 >= 3".to_string()))
+        );
+
+        // still true, but it only stops when it becomes true
+        dbg.resume();
+        assert_eq!(run(&mut dbg, &mut vm, 20), 20);
+
+        // V0 wraps around to 0 and comes back up to 3
+        assert_eq!(run(&mut dbg, &mut vm, 1000), 2 * 246);
+        assert_eq!(vm.reg()[0], 3);
+
+        assert!(parse_condition("V0 >=").is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use std::fmt;
+
+use crate::vm::Vm;
+
+// Condition expressions for conditional breakpoints, e.g. `V3 == 0x10 && I > 0x300`.
+//
+// Operands: V0-VF, I, PC, SP, DT, ST, numbers (decimal or 0x hex) and [expr],
+// the memory byte at an address. Operators, loosest first:
+// `||`, `&&`, `== != < <= > >=`, `+ - &`, `!`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(u32),
+    Reg(Operand),
+    Mem(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.pos + 1, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    pub fn parse(src: &str) -> Result<Expr, ParseError> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: src.len(),
+        };
+
+        let expr = parser.or()?;
+        if let Some(tok) = parser.tokens.get(parser.pos) {
+            return Err(ParseError {
+                pos: tok.pos,
+                message: format!("unexpected `{}`", tok.kind),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    pub fn eval(&self, vm: &Vm) -> u32 {
+        match self {
+            Expr::Num(val) => *val,
+            Expr::Reg(operand) => match operand {
+                Operand::V(idx) => vm.reg()[*idx as usize] as u32,
+                Operand::I => vm.ireg() as u32,
+                Operand::Pc => vm.pc() as u32,
+                Operand::Sp => vm.sp() as u32,
+                Operand::Dt => vm.dt() as u32,
+                Operand::St => vm.st() as u32,
+            },
+            Expr::Mem(addr) => vm
+                .mem()
+                .get(addr.eval(vm) as usize)
+                .map_or(0, |&val| val as u32),
+            Expr::Not(expr) => (expr.eval(vm) == 0) as u32,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(vm);
+                let rhs = rhs.eval(vm);
+
+                match op {
+                    BinOp::Or => (lhs != 0 || rhs != 0) as u32,
+                    BinOp::And => (lhs != 0 && rhs != 0) as u32,
+                    BinOp::Eq => (lhs == rhs) as u32,
+                    BinOp::Ne => (lhs != rhs) as u32,
+                    BinOp::Lt => (lhs < rhs) as u32,
+                    BinOp::Le => (lhs <= rhs) as u32,
+                    BinOp::Gt => (lhs > rhs) as u32,
+                    BinOp::Ge => (lhs >= rhs) as u32,
+                    BinOp::Add => lhs.wrapping_add(rhs),
+                    BinOp::Sub => lhs.wrapping_sub(rhs),
+                    BinOp::BitAnd => lhs & rhs,
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, vm: &Vm) -> bool {
+        self.eval(vm) != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Num(u32),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Num(val) => write!(f, "{}", val),
+            TokenKind::Ident(name) => write!(f, "{}", name),
+            TokenKind::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    pos: usize,
+}
+
+// longest operators first so `<=` isn't read as `<`
+const OPS: [&str; 16] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "&", "!", "(", ")", "[", "]",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let bytes = src.as_bytes();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let ch = bytes[pos];
+        if ch.is_ascii_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        if ch.is_ascii_alphanumeric() || ch == b'_' {
+            let start = pos;
+            while pos < bytes.len() && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_') {
+                pos += 1;
+            }
+
+            let word = &src[start..pos];
+            let kind = if ch.is_ascii_digit() {
+                TokenKind::Num(parse_number(word).ok_or_else(|| ParseError {
+                    pos: start,
+                    message: format!("invalid number `{}`", word),
+                })?)
+            } else {
+                TokenKind::Ident(word.to_string())
+            };
+
+            tokens.push(Token { kind, pos: start });
+            continue;
+        }
+
+        let op = OPS
+            .iter()
+            .find(|op| src[pos..].starts_with(*op))
+            .ok_or_else(|| ParseError {
+                pos,
+                message: format!(
+                    "unexpected character `{}`",
+                    &src[pos..].chars().next().unwrap()
+                ),
+            })?;
+
+        tokens.push(Token {
+            kind: TokenKind::Op(op),
+            pos,
+        });
+        pos += op.len();
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<u32> {
+    match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    }
+}
+
+fn parse_operand(name: &str) -> Option<Operand> {
+    let upper = name.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "PC" => Operand::Pc,
+        "SP" => Operand::Sp,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        _ => {
+            let idx = upper.strip_prefix('V')?;
+            if idx.len() != 1 {
+                return None;
+            }
+            Operand::V(u8::from_str_radix(idx, 16).ok()?)
+        }
+    };
+
+    Some(operand)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // reported as the position of a missing token
+    end: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token {
+                kind: TokenKind::Op(op),
+                ..
+            }) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, ops: &[(&str, BinOp)]) -> Option<BinOp> {
+        let op = self.peek_op()?;
+        let (_, bin_op) = ops.iter().find(|(name, _)| *name == op)?;
+        self.pos += 1;
+        Some(*bin_op)
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[(&str, BinOp)],
+        next: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let mut lhs = next(self)?;
+        while let Some(op) = self.eat(ops) {
+            let rhs = next(self)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        self.binary(&[("||", BinOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        self.binary(&[("&&", BinOp::And)], Self::cmp)
+    }
+
+    fn cmp(&mut self) -> Result<Expr, ParseError> {
+        self.binary(
+            &[
+                ("==", BinOp::Eq),
+                ("!=", BinOp::Ne),
+                ("<", BinOp::Lt),
+                ("<=", BinOp::Le),
+                (">", BinOp::Gt),
+                (">=", BinOp::Ge),
+            ],
+            Self::sum,
+        )
+    }
+
+    fn sum(&mut self) -> Result<Expr, ParseError> {
+        self.binary(
+            &[("+", BinOp::Add), ("-", BinOp::Sub), ("&", BinOp::BitAnd)],
+            Self::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.peek_op() == Some("!") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let end = self.end;
+        let Some(tok) = self.tokens.get(self.pos) else {
+            return Err(ParseError {
+                pos: end,
+                message: "unexpected end of expression".to_string(),
+            });
+        };
+
+        let pos = tok.pos;
+        let expr = match &tok.kind {
+            TokenKind::Num(val) => Expr::Num(*val),
+            TokenKind::Ident(name) => Expr::Reg(parse_operand(name).ok_or_else(|| ParseError {
+                pos,
+                message: format!("unknown register `{}`", name),
+            })?),
+            TokenKind::Op(open @ ("(" | "[")) => {
+                let close = if *open == "(" { ")" } else { "]" };
+                self.pos += 1;
+
+                let inner = self.or()?;
+                if self.peek_op() != Some(close) {
+                    return Err(ParseError {
+                        pos: self.tokens.get(self.pos).map_or(end, |tok| tok.pos),
+                        message: format!("expected `{}`", close),
+                    });
+                }
+
+                if close == ")" {
+                    inner
+                } else {
+                    Expr::Mem(Box::new(inner))
+                }
+            }
+            TokenKind::Op(op) => {
+                return Err(ParseError {
+                    pos,
+                    message: format!("unexpected `{}`", op),
+                });
+            }
+        };
+
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = Expr::parse("V3 == 0x10 && I > 0x300").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Binary(
+                BinOp::And,
+                Box::new(Expr::Binary(
+                    BinOp::Eq,
+                    Box::new(Expr::Reg(Operand::V(3))),
+                    Box::new(Expr::Num(0x10)),
+                )),
+                Box::new(Expr::Binary(
+                    BinOp::Gt,
+                    Box::new(Expr::Reg(Operand::I)),
+                    Box::new(Expr::Num(0x300)),
+                )),
+            )
+        );
+    }
+
+    #[test]
+    fn test_eval() {
+        let vm = Vm::new();
+        assert!(Expr::parse("pc == 0x200 && !(vf)").unwrap().is_true(&vm));
+        assert!(Expr::parse("[0] == 0xf0 || V0 > 1").unwrap().is_true(&vm));
+        assert!(!Expr::parse("SP + 1 <= 0").unwrap().is_true(&vm));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Expr::parse("V3 ==").unwrap_err().pos, 5);
+        assert_eq!(Expr::parse("VG == 1").unwrap_err().pos, 0);
+        assert_eq!(Expr::parse("(V1 == 1").unwrap_err().pos, 8);
+    }
+}
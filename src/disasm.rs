@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::vm::instruction::{self, Instruction};
+
+const ROM_START: usize = 0x200;
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    Unknown,
+    // first byte of an instruction
+    Code,
+    // the rest of an instruction
+    Operand,
+    Sprite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Sub,
+    Jump,
+    Sprite,
+}
+
+// Recursive descent disassembler. Code is whatever is reachable from 0x200
+// through jumps, calls and both sides of skips, sprite data is found by
+// following I from Annn to the Dxyn that draws it. Everything else is `db`.
+pub struct Disassembler<'a> {
+    rom: &'a [u8],
+    bytes: Vec<Byte>,
+    labels: BTreeMap<u16, Label>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(rom: &'a [u8]) -> Self {
+        let mut disasm = Self {
+            rom,
+            bytes: vec![Byte::Unknown; rom.len()],
+            labels: BTreeMap::new(),
+        };
+
+        disasm.walk();
+        disasm
+    }
+
+    fn byte(&self, addr: u16) -> Option<u8> {
+        self.rom_byte(addr as usize)
+    }
+
+    // for the listing, which runs up to 0x10000
+    fn rom_byte(&self, addr: usize) -> Option<u8> {
+        addr.checked_sub(ROM_START)
+            .and_then(|idx| self.rom.get(idx).copied())
+    }
+
+    fn opcode(&self, addr: u16) -> Option<u16> {
+        Some(u16::from_be_bytes([
+            self.byte(addr)?,
+            self.byte(addr.wrapping_add(1))?,
+        ]))
+    }
+
+    fn kind(&self, addr: u16) -> Byte {
+        (addr as usize)
+            .checked_sub(ROM_START)
+            .and_then(|idx| self.bytes.get(idx).copied())
+            .unwrap_or(Byte::Unknown)
+    }
+
+    fn mark(&mut self, addr: u16, kind: Byte) {
+        if let Some(byte) = (addr as usize)
+            .checked_sub(ROM_START)
+            .and_then(|idx| self.bytes.get_mut(idx))
+        {
+            *byte = kind;
+        }
+    }
+
+    // a call target stays a subroutine even if something also jumps there
+    fn add_label(&mut self, addr: u16, label: Label) {
+        if self.byte(addr).is_some() {
+            let entry = self.labels.entry(addr).or_insert(label);
+            *entry = (*entry).min(label);
+        }
+    }
+
+    // every work item carries the value of I known at that point, if any
+    fn walk(&mut self) {
+        let mut pending = vec![(ROM_START as u16, None)];
+        let mut visited = BTreeSet::new();
+
+        while let Some((mut addr, mut ireg)) = pending.pop() {
+            while visited.insert(addr) {
+                let Some(opcode) = self.opcode(addr) else {
+                    break;
+                };
+
+                let ins = instruction::decode(opcode);
+                if let Instruction::Unknown(_) = ins {
+                    break;
+                }
+
+                // F000 nnnn is only complete with its operand word
+                let long_addr = match ins {
+                    Instruction::LdILong => match self.opcode(addr.wrapping_add(2)) {
+                        Some(long_addr) => Some(long_addr),
+                        None => break,
+                    },
+                    _ => None,
+                };
+
+                self.mark(addr, Byte::Code);
+                for offset in 1..ins.size() {
+                    self.mark(addr.wrapping_add(offset), Byte::Operand);
+                }
+
+                let next = addr.wrapping_add(ins.size());
+                match ins {
+                    Instruction::Jp(target) => {
+                        self.add_label(target, Label::Jump);
+                        pending.push((target, ireg));
+                        break;
+                    }
+                    Instruction::Call(target) => {
+                        self.add_label(target, Label::Sub);
+                        pending.push((target, ireg));
+                    }
+                    Instruction::Ret | Instruction::Exit => break,
+                    // the target depends on a register, nothing to follow
+                    Instruction::JpV0 { addr: target, .. } => {
+                        self.add_label(target, Label::Jump);
+                        break;
+                    }
+                    Instruction::SeImm { .. }
+                    | Instruction::SneImm { .. }
+                    | Instruction::SeReg { .. }
+                    | Instruction::SneReg { .. }
+                    | Instruction::Skp(_)
+                    | Instruction::Sknp(_) => {
+                        let skipped = self
+                            .opcode(next)
+                            .map_or(2, |op| instruction::decode(op).size());
+                        pending.push((next.wrapping_add(skipped), ireg));
+                    }
+                    Instruction::LdI(target) => ireg = Some(target),
+                    Instruction::LdILong => ireg = long_addr,
+                    Instruction::AddI(_)
+                    | Instruction::LdF(_)
+                    | Instruction::LdHf(_)
+                    | Instruction::LdIVx(_)
+                    | Instruction::LdVxI(_) => ireg = None,
+                    Instruction::Drw { n, .. } => {
+                        if let Some(sprite) = ireg {
+                            self.mark_sprite(sprite, if n == 0 { 32 } else { n as u16 });
+                        }
+                    }
+                    _ => {}
+                }
+
+                addr = next;
+            }
+        }
+    }
+
+    // bytes already decoded as code stay code, a sprite drawn again with more
+    // rows grows
+    fn mark_sprite(&mut self, addr: u16, len: u16) {
+        if matches!(self.kind(addr), Byte::Code | Byte::Operand) {
+            return;
+        }
+
+        self.add_label(addr, Label::Sprite);
+        for offset in 0..len {
+            let byte_addr = addr.wrapping_add(offset);
+            if self.kind(byte_addr) == Byte::Unknown {
+                self.mark(byte_addr, Byte::Sprite);
+            }
+        }
+    }
+
+    fn label_name(&self, addr: u16) -> Option<String> {
+        let name = match self.labels.get(&addr)? {
+            Label::Sub => format!("sub_{:03x}", addr),
+            Label::Jump => format!("L{:03x}", addr),
+            Label::Sprite => format!("sprite_{:03x}", addr),
+        };
+
+        Some(name)
+    }
+
+    // Cowgod text with known addresses replaced by their labels
+    fn format(&self, ins: Instruction, addr: u16) -> String {
+        let name = |target: u16| {
+            self.label_name(target)
+                .unwrap_or_else(|| format!("0x{:03x}", target))
+        };
+
+        match ins {
+            Instruction::Jp(target) => format!("JP {}", name(target)),
+            Instruction::Call(target) => format!("CALL {}", name(target)),
+            Instruction::LdI(target) => format!("LD I, {}", name(target)),
+            Instruction::JpV0 { addr: target, .. } => format!("JP V0, {}", name(target)),
+            Instruction::LdILong => {
+                let target = self.opcode(addr.wrapping_add(2)).unwrap_or(0);
+                format!("LD I, long {}", name(target))
+            }
+            _ => ins.to_string(),
+        }
+    }
+
+    pub fn listing(&self) -> String {
+        let mut out = String::new();
+        // a ROM can fill memory up to 0xffff, so the end doesn't fit in a u16
+        let end = ROM_START + self.rom.len();
+        let mut addr = ROM_START;
+
+        while addr < end {
+            if let Some(label) = self.label_name(addr as u16) {
+                _ = writeln!(out, "\n{}:", label);
+            }
+
+            match self.kind(addr as u16) {
+                Byte::Code => {
+                    let ins = instruction::decode(self.opcode(addr as u16).unwrap_or(0));
+                    let size = ins.size() as usize;
+                    let raw: String = (addr..addr + size)
+                        .map(|addr| format!("{:02x}", self.rom_byte(addr).unwrap_or(0)))
+                        .collect();
+
+                    _ = writeln!(
+                        out,
+                        "    {:<28}; {:03x}  {}",
+                        self.format(ins, addr as u16),
+                        addr,
+                        raw
+                    );
+                    addr += size;
+                }
+                Byte::Sprite => {
+                    let byte = self.rom_byte(addr).unwrap_or(0);
+                    let row: String = (0..8)
+                        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                        .collect();
+
+                    _ = writeln!(out, "    {:<28}; {}", format!("db 0x{:02x}", byte), row);
+                    addr += 1;
+                }
+                // operands are only reached on their own when code overlaps
+                Byte::Unknown | Byte::Operand => {
+                    let mut data = Vec::new();
+                    while addr < end
+                        && data.len() < DATA_PER_LINE
+                        && matches!(self.kind(addr as u16), Byte::Unknown | Byte::Operand)
+                        && (data.is_empty() || !self.labels.contains_key(&(addr as u16)))
+                    {
+                        data.push(format!("0x{:02x}", self.rom_byte(addr).unwrap_or(0)));
+                        addr += 1;
+                    }
+
+                    _ = writeln!(out, "    db {}", data.join(", "));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+pub fn disassemble(rom: &[u8]) -> String {
+    Disassembler::new(rom).listing()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_sprites_and_data() {
+        let rom = [
+            0xa2, 0x0c, // 200: LD I, sprite_20c
+            0x22, 0x08, // 202: CALL sub_208
+            0x12, 0x04, // 204: JP L204
+            0xff, 0xff, // 206: unreachable
+            0xd0, 0x12, // 208: DRW V0, V1, 2
+            0x00, 0xee, // 20a: RET
+            0x3c, 0x42, // 20c: sprite
+        ];
+
+        let listing = disassemble(&rom);
+        assert!(listing.contains("LD I, sprite_20c"));
+        assert!(listing.contains("CALL sub_208"));
+        assert!(listing.contains("L204:\n    JP L204"));
+        assert!(listing.contains("db 0xff, 0xff\n"));
+        assert!(listing.contains("RET"));
+        assert!(listing.contains("; ..####.."));
+        assert!(listing.contains("; .#....#."));
+    }
+
+    #[test]
+    fn test_sprite_drawn_again() {
+        let rom = [
+            0xa2, 0x08, // 200: LD I, sprite_208
+            0xd0, 0x11, // 202: DRW V0, V1, 1
+            0xd0, 0x12, // 204: DRW V0, V1, 2
+            0x12, 0x06, // 206: JP L206
+            0x3c, 0x42, // 208: sprite
+        ];
+
+        let listing = disassemble(&rom);
+        assert!(listing.contains("; ..####.."));
+        assert!(listing.contains("; .#....#."), "{}", listing);
+    }
+
+    #[test]
+    fn test_full_memory() {
+        // CLS all the way up to F000 nnnn at 0xfffc, the last instruction
+        let mut rom = [0x00, 0xe0].repeat((0x10000 - ROM_START) / 2);
+        let len = rom.len();
+        rom[len - 4..].copy_from_slice(&[0xf0, 0x00, 0x12, 0x34]);
+
+        let listing = disassemble(&rom);
+        assert!(listing.trim_end().ends_with("; fffc  f0001234"));
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::trace::Tracer;
+use crate::video_buffer::VideoBuffer;
+use crate::vm::{Vm, VmError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+// Why a Runner stopped early
+#[derive(Debug)]
+pub enum RunError {
+    Vm(VmError),
+    // writing the trace failed, the run stops rather than leave a gap in it
+    Trace(io::Error),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Vm(err) => write!(f, "{}", err),
+            RunError::Trace(err) => write!(f, "trace failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+impl From<VmError> for RunError {
+    fn from(err: VmError) -> Self {
+        RunError::Vm(err)
+    }
+}
+
+// Input script, one event per line: `<frame> <key> down|up`, `#` starts a comment.
+//
+//   # hold 5 for half a second
+//   10 5 down
+//   40 5 up
+pub fn parse_script(text: &str) -> Result<Vec<InputEvent>, ScriptError> {
+    let mut events = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let event = parse_event(line).map_err(|message| ScriptError {
+            line: idx + 1,
+            message,
+        })?;
+        events.push(event);
+    }
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+// one `<frame> <key> down|up` line, without comments
+pub fn parse_event(line: &str) -> Result<InputEvent, String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let [frame, key, state] = parts[..] else {
+        return Err(format!("expected `<frame> <key> down|up`, got `{}`", line));
+    };
+
+    let frame = frame
+        .parse::<u64>()
+        .map_err(|_| format!("invalid frame number `{}`", frame))?;
+
+    let key = usize::from_str_radix(key, 16)
+        .ok()
+        .filter(|&key| key < 16)
+        .ok_or_else(|| format!("invalid key `{}`, expected 0-F", key))?;
+
+    let pressed = match state {
+        "down" | "press" => true,
+        "up" | "release" => false,
+        _ => return Err(format!("invalid key state `{}`", state)),
+    };
+
+    Ok(InputEvent {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+// written back the way parse_event reads it
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.pressed { "down" } else { "up" };
+        write!(f, "{} {:X} {}", self.frame, self.key, state)
+    }
+}
+
+// Drives a Vm with the same cadence as App: ticks_per_frame instructions,
+// then the 60Hz timers.
+pub struct Runner {
+    pub vm: Vm,
+    pub tracer: Option<Tracer>,
+    ticks_per_frame: u32,
+    events: Vec<InputEvent>,
+    next_event: usize,
+    frame: u64,
+    cycles: u64,
+}
+
+impl Runner {
+    pub fn new(vm: Vm, ticks_per_frame: u32, events: Vec<InputEvent>) -> Self {
+        Self {
+            vm,
+            tracer: None,
+            ticks_per_frame: ticks_per_frame.max(1),
+            events,
+            next_event: 0,
+            frame: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn run_frames(&mut self, frames: u64) -> Result<(), RunError> {
+        for _ in 0..frames {
+            if self.vm.exited() {
+                break;
+            }
+
+            self.run_cycles(self.ticks_per_frame as u64)?;
+        }
+
+        Ok(())
+    }
+
+    // timers tick whenever a frame's worth of cycles has been executed
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<(), RunError> {
+        for _ in 0..cycles {
+            if self.vm.exited() {
+                break;
+            }
+
+            if self.cycles.is_multiple_of(self.ticks_per_frame as u64) {
+                self.apply_input();
+            }
+
+            if let Some(tracer) = &mut self.tracer {
+                tracer.before_tick(&self.vm);
+            }
+
+            self.vm.tick()?;
+            self.cycles += 1;
+
+            if let Some(tracer) = &mut self.tracer {
+                tracer.after_tick(&self.vm).map_err(RunError::Trace)?;
+            }
+
+            if self.cycles.is_multiple_of(self.ticks_per_frame as u64) {
+                self.vm.delay_timer();
+                self.vm.sound_timer();
+                self.frame += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_input(&mut self) {
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+
+            self.vm.set_kb(event.key, event.pressed);
+            self.next_event += 1;
+        }
+    }
+}
+
+pub fn screen_text(vb: &VideoBuffer) -> String {
+    const CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+    let mut out = String::with_capacity((vb.width() + 1) * vb.height());
+    for y in 0..vb.height() {
+        for x in 0..vb.width() {
+            out.push(CHARS[vb.color_index(x, y) as usize]);
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+pub fn write_png(vb: &VideoBuffer, palette: &[[f32; 4]; 4], path: &Path) -> io::Result<()> {
+    let file = std::fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(
+        io::BufWriter::new(file),
+        vb.width() as u32,
+        vb.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data = Vec::with_capacity(vb.width() * vb.height() * 3);
+    for y in 0..vb.height() {
+        for x in 0..vb.width() {
+            let color = palette[vb.color_index(x, y) as usize];
+            data.extend(color[..3].iter().map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8));
+        }
+    }
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    Ok(())
+}
+
+pub fn registers_json(runner: &Runner) -> String {
+    let vm = &runner.vm;
+    let join = |vals: &mut dyn Iterator<Item = String>| vals.collect::<Vec<_>>().join(", ");
+
+    let stack = &vm.stack()[..vm.sp() as usize];
+    format!(
+        concat!(
+            "{{\n",
+            "  \"pc\": {},\n",
+            "  \"i\": {},\n",
+            "  \"v\": [{}],\n",
+            "  \"sp\": {},\n",
+            "  \"stack\": [{}],\n",
+            "  \"dt\": {},\n",
+            "  \"st\": {},\n",
+            "  \"frames\": {},\n",
+            "  \"cycles\": {},\n",
+            "  \"exited\": {}\n",
+            "}}\n"
+        ),
+        vm.pc(),
+        vm.ireg(),
+        join(&mut vm.reg().iter().map(|v| v.to_string())),
+        vm.sp(),
+        join(&mut stack.iter().map(|v| v.to_string())),
+        vm.dt(),
+        vm.st(),
+        runner.frame(),
+        runner.cycles(),
+        vm.exited(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let events = parse_script(
+            "# hold 5, then tap a\n\
+             40 5 up\n\
+             10 5 down  # press\n\
+             \n\
+             12 A press\n\
+             13 a release\n",
+        )
+        .unwrap();
+        let lines: Vec<String> = events.iter().map(|event| event.to_string()).collect();
+        assert_eq!(lines, ["10 5 down", "12 A down", "13 A up", "40 5 up"]);
+
+        // written back, it reads the same
+        assert_eq!(parse_script(&lines.join("\n")).unwrap(), events);
+
+        let err = parse_script("1 2 down\n\n3 G down\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(parse_script("1 2").is_err());
+        assert!(parse_script("x 2 down").is_err());
+        assert!(parse_script("1 2 held").is_err());
+    }
+
+    #[test]
+    fn test_runner_cadence() {
+        // V0 = 5, DT = V0, then spin until key V0 is down
+        let vm = Vm::from_bytes(&[0x60, 0x05, 0xf0, 0x15, 0xe0, 0x9e, 0x12, 0x04, 0x12, 0x08]);
+        let events = parse_script("1 5 down").unwrap();
+        let mut runner = Runner::new(vm, 10, events);
+
+        runner.run_cycles(5).unwrap();
+        assert_eq!((runner.frame(), runner.cycles()), (0, 5));
+        assert_eq!(runner.vm.dt(), 5);
+
+        // the timers count down once a frame's cycles are done
+        runner.run_cycles(5).unwrap();
+        assert_eq!((runner.frame(), runner.cycles()), (1, 10));
+        assert_eq!(runner.vm.dt(), 4);
+        assert!(matches!(runner.vm.pc(), 0x204 | 0x206));
+
+        // input for frame 1 is there from its first cycle
+        runner.run_frames(2).unwrap();
+        assert_eq!((runner.frame(), runner.cycles()), (3, 30));
+        assert_eq!(runner.vm.dt(), 2);
+        assert_eq!(runner.vm.pc(), 0x208);
+    }
+}
@@ -1,8 +1,22 @@
+#[cfg(feature = "gui")]
 pub mod app;
+pub mod asm;
+#[cfg(feature = "gui")]
 pub mod beeper;
+pub mod debugger;
+pub mod disasm;
+#[cfg(feature = "gui")]
 pub mod egui;
+pub mod headless;
+pub mod movie;
+pub mod octo;
+pub mod rewind;
+pub mod romdb;
 pub mod settings;
+pub mod trace;
+#[cfg(feature = "gui")]
 pub mod ui;
 pub mod video_buffer;
 pub mod vm;
+#[cfg(feature = "gui")]
 pub mod wgpu_ctx;
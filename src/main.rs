@@ -1,37 +1,370 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use std::{
     error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
 };
 use winit::event_loop::{ControlFlow, EventLoop};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(author, version, about, args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(index = 1, help = "ROM")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(index = 1, help = "ROM, or Octo source (.8o) to compile")]
     rom_path: Option<String>,
+
+    #[arg(
+        long,
+        help = "Seed for RND (Cxkk), runs with the same seed and input are identical [default: random]"
+    )]
+    seed: Option<u64>,
+
+    #[command(flatten)]
+    settings: SettingsArgs,
+
+    #[arg(long, help = "Start fullscreen")]
+    fullscreen: bool,
+
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        value_parser = parse_window_size,
+        help = "Window size in logical pixels [default: 1280x640]"
+    )]
+    window_size: Option<(u32, u32)>,
+
+    #[arg(long, help = "Start paused in the debugger")]
+    paused: bool,
+
+    #[arg(long = "break", value_parser = debugger::parse_addr, help = "Breakpoint address, repeatable")]
+    breakpoints: Vec<u16>,
+
+    #[arg(long = "watch", value_parser = debugger::parse_watch, help = "Watch memory, `start[-end][:r|w|rw]`, repeatable")]
+    watchpoints: Vec<Watchpoint>,
+
+    #[arg(long = "break-if", value_parser = debugger::parse_condition, help = "Break when a condition becomes true, e.g. `V3 == 0x10 && I > 0x300`, repeatable")]
+    conditions: Vec<Condition>,
+
+    #[command(flatten)]
+    trace: TraceArgs,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "rom_path",
+        help = "Record the keypad into a movie, saved on exit"
+    )]
+    record: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "rom_path",
+        conflicts_with_all = ["record", "quirks", "seed", "speed"],
+        help = "Play back a movie, with its seed, quirks and speed"
+    )]
+    replay: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Disassemble a ROM into Cowgod-style mnemonics")]
+    Disasm {
+        #[arg(index = 1, help = "ROM")]
+        rom_path: PathBuf,
+
+        #[arg(short, long, help = "Write the listing to a file instead of stdout")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Assemble Cowgod-style source into a ROM")]
+    Asm {
+        #[arg(index = 1, help = "Source file")]
+        source: PathBuf,
+
+        #[arg(
+            short,
+            long,
+            help = "ROM to write, defaults to the source with a .ch8 extension"
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(long, help = "Also write a symbol map of labels and constants")]
+        symbols: Option<PathBuf>,
+    },
+
+    #[command(about = "Print a trace written by --trace as text")]
+    TraceDump {
+        #[arg(index = 1, help = "Trace file")]
+        path: PathBuf,
+
+        #[arg(short, long, help = "Write the text to a file instead of stdout")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Run a ROM and compare each instruction with another emulator's log")]
+    TraceDiff {
+        #[arg(index = 1, help = "ROM")]
+        rom_path: PathBuf,
+
+        #[arg(
+            index = 2,
+            help = "Reference log, a line of PC, OP, V0-VF, I and SP fields per instruction"
+        )]
+        reference: PathBuf,
+
+        #[arg(long, value_enum, help = "Quirks preset")]
+        quirks: Option<QuirksPreset>,
+
+        #[arg(long, help = "Instructions per 60Hz frame [default: same as the GUI]")]
+        ticks_per_frame: Option<u32>,
+
+        #[arg(long, help = "Seed for RND (Cxkk) [default: random]")]
+        seed: Option<u64>,
+
+        #[arg(
+            long,
+            default_value_t = 8,
+            help = "Instructions to show before the divergence"
+        )]
+        context: usize,
+
+        #[arg(long, help = "Also write this emulator's side in the reference format")]
+        log: Option<PathBuf>,
+    },
 }
 
 use chip8::app::App;
-use chip8::settings::Settings;
-use chip8::vm::Vm;
+use chip8::asm;
+use chip8::debugger::{self, Condition, Debugger, Watchpoint};
+use chip8::disasm;
+use chip8::headless::Runner;
+use chip8::movie::{Movie, Player, Recorder};
+use chip8::octo;
+use chip8::settings::config::{self, Config};
+use chip8::settings::{Settings, SettingsArgs};
+use chip8::trace::{TraceArgs, TraceReader, diff};
+use chip8::vm::{QuirksPreset, Vm};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
+
     let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let settings = Arc::new(RwLock::new(Settings::new()));
+    let mut settings = Settings::load(args.settings.config_path());
+
+    let movie = match &args.replay {
+        Some(path) => Some(Movie::parse(&std::fs::read_to_string(path)?)?),
+        None => None,
+    };
 
     let mut vm = Vm::new();
+    if let Some(seed) = args.seed {
+        vm.set_seed(seed);
+    }
+
+    let mut octo_program = None;
     if let Some(rom_path) = &args.rom_path {
-        vm.load_rom(rom_path.clone())?;
+        if octo::is_octo_source(Path::new(rom_path)) {
+            let program = match octo::compile_file(Path::new(rom_path)) {
+                Ok(program) => program,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            vm.load_rom_bytes(rom_path.clone(), &program.rom)?;
+            octo_program = Some(program);
+        } else {
+            vm.load_rom(rom_path.clone())?;
+        }
+    }
+
+    // the command line wins over what's known or saved for the ROM
+    settings.load_rom(&vm);
+    settings.pin(&args.settings.config());
+    if let Some(movie) = &movie {
+        settings.pin(&Config {
+            quirks: Some(movie.quirks),
+            ticks_per_frame: Some(movie.ticks_per_frame),
+            ..Default::default()
+        });
+    }
+
+    if let (Some(movie), Some(path)) = (&movie, &args.replay) {
+        if !movie.matches_rom(&vm) {
+            eprintln!("{}: recorded with another ROM", path.display());
+            std::process::exit(1);
+        }
+        vm.set_seed(movie.seed);
+    }
+
+    let recorder = match args.record {
+        Some(path) => {
+            let recorder = Recorder::new(&vm, settings.quirks, settings.ticks_per_frame)
+                .ok_or("--record needs a ROM")?;
+            Some((recorder, path))
+        }
+        None => None,
+    };
+
+    let settings = Arc::new(RwLock::new(settings));
+
+    let mut debugger = Debugger::new();
+    if args.paused {
+        debugger.pause();
+    }
+
+    for addr in args.breakpoints {
+        debugger.add_breakpoint(addr);
+    }
+
+    for watchpoint in args.watchpoints {
+        debugger.add_watchpoint(watchpoint);
+    }
+
+    for condition in args.conditions {
+        debugger.add_condition(condition);
+    }
+
+    // Octo's `:breakpoint` and `:monitor` directives
+    if let Some(program) = octo_program {
+        for (_, addr) in program.breakpoints {
+            debugger.add_breakpoint(addr);
+        }
+
+        for monitor in program.monitors {
+            debugger.add_monitor(monitor);
+        }
+    }
+
+    let mut app = App::new(vm, settings, debugger);
+    app.set_fullscreen(args.fullscreen);
+    if let Some((width, height)) = args.window_size {
+        app.set_window_size(width, height);
+    }
+
+    if let Some(tracer) = args.trace.tracer()? {
+        app.set_tracer(tracer);
+    }
+
+    if let Some((recorder, path)) = recorder {
+        app.set_recorder(recorder, path);
+    }
+
+    if let Some(movie) = &movie {
+        app.set_player(Player::new(movie));
     }
 
-    let mut app = App::new(vm, settings);
     event_loop.run_app(&mut app)?;
 
     Ok(())
 }
+
+// `800x400`
+fn parse_window_size(s: &str) -> Result<(u32, u32), String> {
+    let err = || format!("expected WIDTHxHEIGHT, got `{}`", s);
+    let (width, height) = s.split_once(['x', 'X']).ok_or_else(err)?;
+    let width: u32 = width.trim().parse().map_err(|_| err())?;
+    let height: u32 = height.trim().parse().map_err(|_| err())?;
+    if width == 0 || height == 0 {
+        return Err(err());
+    }
+
+    Ok((width, height))
+}
+
+fn run_command(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Disasm { rom_path, output } => {
+            let listing = disasm::disassemble(&std::fs::read(&rom_path)?);
+            match output {
+                Some(path) => std::fs::write(path, listing)?,
+                None => print!("{}", listing),
+            }
+        }
+        Command::Asm {
+            source,
+            output,
+            symbols,
+        } => {
+            // compiler-style `file:line:col: message` rather than the Debug output
+            let program = match asm::assemble_file(&source) {
+                Ok(program) => program,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            };
+            let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+            std::fs::write(&output, &program.rom)?;
+            if let Some(path) = symbols {
+                std::fs::write(path, program.symbol_map())?;
+            }
+
+            println!("{}: {} bytes", output.display(), program.rom.len());
+        }
+        Command::TraceDump { path, output } => {
+            let data = std::fs::read(&path)?;
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(BufWriter::new(std::io::stdout().lock())),
+            };
+
+            for record in TraceReader::new(&data)? {
+                writeln!(out, "{}", record?)?;
+            }
+            out.flush()?;
+        }
+        Command::TraceDiff {
+            rom_path,
+            reference,
+            quirks,
+            ticks_per_frame,
+            seed,
+            context,
+            log,
+        } => {
+            let mut settings = Settings::load(config::default_path());
+            let reference = diff::parse_reference(&std::fs::read_to_string(&reference)?)?;
+
+            let mut vm = Vm::new();
+            if let Some(seed) = seed {
+                vm.set_seed(seed);
+            }
+            vm.load_rom(rom_path.display().to_string())?;
+            settings.load_rom(&vm);
+            vm.set_quirks(quirks.map_or(settings.quirks, |preset| preset.quirks()));
+            let ticks_per_frame = ticks_per_frame.unwrap_or(settings.ticks_per_frame);
+            let mut runner = Runner::new(vm, ticks_per_frame, Vec::new());
+
+            let mut lines = Vec::new();
+            let report = diff::diff(&mut runner, &reference, context, |snapshot| {
+                if log.is_some() {
+                    lines.push(format!("{}\n", snapshot));
+                }
+            });
+
+            if let Some(path) = log {
+                std::fs::write(path, lines.concat())?;
+            }
+
+            print!("{}", report);
+            if !report.matched() {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,377 @@
+use std::fmt;
+
+use crate::headless::{self, InputEvent, ScriptError};
+use crate::vm::{Quirks, Vm};
+
+// Movie layout, plain text so it can be attached to a bug report and diffed:
+//
+//   chip8-movie 1
+//   rom <sha1 of the ROM>
+//   seed <u64>
+//   quirks <names of the quirks that are on>
+//   ticks_per_frame <n>
+//   frames <length>
+//   <frame> <key> down|up      one line per keypad change, like input scripts
+//
+// `#` starts a comment. Playing it back from a fresh VM with the same ROM,
+// seed, quirks and ticks_per_frame gives the same run.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub ticks_per_frame: u32,
+    // frames recorded, events come before the end
+    pub frames: u64,
+    pub events: Vec<InputEvent>,
+}
+
+fn quirk_flags(quirks: &mut Quirks) -> [(&'static str, &mut bool); 5] {
+    [
+        ("shift_uses_vy", &mut quirks.shift_uses_vy),
+        ("load_store_inc_i", &mut quirks.load_store_inc_i),
+        ("jump_with_vx", &mut quirks.jump_with_vx),
+        ("vf_reset", &mut quirks.vf_reset),
+        ("clip_sprites", &mut quirks.clip_sprites),
+    ]
+}
+
+impl Movie {
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut quirks = None;
+        let mut ticks_per_frame = None;
+        let mut frames = None;
+        let mut events = Vec::new();
+        let mut version = None;
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = |message: String| ScriptError {
+                line: idx + 1,
+                message,
+            };
+            let number = |val: &str| {
+                val.parse::<u64>()
+                    .map_err(|_| err(format!("invalid number `{}`", val)))
+            };
+
+            let (key, val) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let val = val.trim();
+            match key {
+                "chip8-movie" => {
+                    let found = number(val)?;
+                    if found != VERSION as u64 {
+                        return Err(err(format!("unsupported movie version {}", found)));
+                    }
+                    version = Some(found);
+                }
+                "rom" => rom_hash = Some(val.to_string()),
+                "seed" => seed = Some(number(val)?),
+                "ticks_per_frame" => {
+                    let val = u32::try_from(number(val)?)
+                        .map_err(|_| err("ticks_per_frame is too large".to_string()))?;
+                    ticks_per_frame = Some(val);
+                }
+                "frames" => frames = Some(number(val)?),
+                "quirks" => {
+                    let mut parsed = Quirks::default();
+                    for name in val.split_whitespace() {
+                        let mut flags = quirk_flags(&mut parsed);
+                        let (_, flag) = flags
+                            .iter_mut()
+                            .find(|(flag_name, _)| *flag_name == name)
+                            .ok_or_else(|| err(format!("unknown quirk `{}`", name)))?;
+                        **flag = true;
+                    }
+                    quirks = Some(parsed);
+                }
+                _ => events.push(headless::parse_event(line).map_err(err)?),
+            }
+        }
+
+        // not about any line in particular
+        let missing = |what: &str| ScriptError {
+            line: 0,
+            message: format!("not a movie, `{}` is missing", what),
+        };
+        version.ok_or_else(|| missing("chip8-movie"))?;
+        events.sort_by_key(|event| event.frame);
+
+        Ok(Self {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            ticks_per_frame: ticks_per_frame.ok_or_else(|| missing("ticks_per_frame"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            events,
+        })
+    }
+
+    pub fn matches_rom(&self, vm: &Vm) -> bool {
+        vm.rom_hash() == Some(self.rom_hash.as_str())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut quirks = self.quirks;
+        let names: Vec<&str> = quirk_flags(&mut quirks)
+            .into_iter()
+            .filter(|(_, flag)| **flag)
+            .map(|(name, _)| name)
+            .collect();
+
+        writeln!(f, "chip8-movie {}", VERSION)?;
+        writeln!(f, "rom {}", self.rom_hash)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "quirks {}", names.join(" "))?;
+        writeln!(f, "ticks_per_frame {}", self.ticks_per_frame)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Collects keypad changes as they happen. The frame count has to follow the
+// VM's frames exactly, frames cut short by the debugger don't count.
+#[derive(Debug)]
+pub struct Recorder {
+    movie: Movie,
+    kb: [bool; 16],
+}
+
+impl Recorder {
+    // None without a ROM to tie the movie to
+    pub fn new(vm: &Vm, quirks: Quirks, ticks_per_frame: u32) -> Option<Self> {
+        let movie = Movie {
+            rom_hash: vm.rom_hash()?.to_string(),
+            seed: vm.seed(),
+            quirks,
+            ticks_per_frame,
+            frames: 0,
+            events: Vec::new(),
+        };
+
+        Some(Self {
+            movie,
+            kb: [false; 16],
+        })
+    }
+
+    // whether the movie can still be replayed with these settings
+    pub fn matches(&self, quirks: Quirks, ticks_per_frame: u32) -> bool {
+        self.movie.quirks == quirks && self.movie.ticks_per_frame == ticks_per_frame
+    }
+
+    // key repeats and other non-changes are dropped
+    pub fn key(&mut self, key: usize, pressed: bool) {
+        if self.kb[key] == pressed {
+            return;
+        }
+
+        self.kb[key] = pressed;
+        self.movie.events.push(InputEvent {
+            frame: self.movie.frames,
+            key,
+            pressed,
+        });
+    }
+
+    pub fn end_frame(&mut self) {
+        self.movie.frames += 1;
+    }
+
+    // rewinding a frame takes it out of the movie, with its input
+    pub fn rewind_frame(&mut self) {
+        self.movie.frames = self.movie.frames.saturating_sub(1);
+
+        let frames = self.movie.frames;
+        self.movie.events.retain(|event| event.frame < frames);
+
+        // the keypad isn't rewound, what is held now is held from here on
+        let mut held = [false; 16];
+        for event in &self.movie.events {
+            held[event.key] = event.pressed;
+        }
+        for key in (0..16).filter(|&key| held[key] != self.kb[key]) {
+            self.movie.events.push(InputEvent {
+                frame: frames,
+                key,
+                pressed: self.kb[key],
+            });
+        }
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+// Feeds a movie's input to a Vm, the same way headless::Runner does.
+#[derive(Debug)]
+pub struct Player {
+    events: Vec<InputEvent>,
+    next_event: usize,
+    frame: u64,
+    frames: u64,
+}
+
+impl Player {
+    pub fn new(movie: &Movie) -> Self {
+        Self {
+            events: movie.events.clone(),
+            next_event: 0,
+            frame: 0,
+            frames: movie.frames,
+        }
+    }
+
+    // call before running a frame
+    pub fn apply(&mut self, vm: &mut Vm) {
+        while let Some(event) = self.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+
+            vm.set_kb(event.key, event.pressed);
+            self.next_event += 1;
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Runner;
+
+    #[test]
+    fn test_roundtrip() {
+        // Cxkk and the key in V1 make the run depend on both seed and input
+        let rom = [
+            0xc0, 0xff, // 200: RND V0, 0xff
+            0xf1, 0x0a, // 202: LD V1, K
+            0x12, 0x00, // 204: JP 0x200
+        ];
+        let mut vm = Vm::new();
+        vm.load_rom_bytes("test.ch8".to_string(), &rom).unwrap();
+        vm.set_seed(99);
+
+        let quirks = Quirks {
+            vf_reset: true,
+            clip_sprites: true,
+            ..Quirks::default()
+        };
+        let mut recorder = Recorder::new(&vm, quirks, 4).unwrap();
+        recorder.end_frame();
+        recorder.key(7, true);
+        recorder.key(7, true);
+        recorder.end_frame();
+        recorder.end_frame();
+        recorder.key(7, false);
+        recorder.key(2, true);
+        // the frame goes, the keys stay as they are
+        recorder.rewind_frame();
+        recorder.key(7, false);
+        recorder.end_frame();
+        recorder.end_frame();
+        let movie = recorder.finish();
+
+        let text = movie.to_string();
+        assert_eq!(
+            text,
+            format!(
+                "chip8-movie 1\nrom {}\nseed 99\nquirks vf_reset clip_sprites\n\
+                 ticks_per_frame 4\nframes 4\n1 7 down\n2 2 down\n2 7 up\n",
+                vm.rom_hash().unwrap()
+            )
+        );
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn test_replay() {
+        // Cxkk and the key in V1 make the run depend on both seed and input
+        let rom = [
+            0xc0, 0xff, // 200: RND V0, 0xff
+            0xf1, 0x0a, // 202: LD V1, K
+            0x12, 0x00, // 204: JP 0x200
+        ];
+        let load = || {
+            let mut vm = Vm::new();
+            vm.load_rom_bytes("test.ch8".to_string(), &rom).unwrap();
+            vm.set_seed(99);
+            vm
+        };
+
+        // recorded the way App does it, keys first, then the frame's ticks
+        let mut vm = load();
+        let mut recorder = Recorder::new(&vm, Quirks::default(), 4).unwrap();
+        let mut last_v1 = Vec::new();
+        for frame in 0..6 {
+            let keys: &[(usize, bool)] = match frame {
+                1 => &[(7, true)],
+                3 => &[(7, false), (2, true)],
+                _ => &[],
+            };
+            for &(key, pressed) in keys {
+                vm.set_kb(key, pressed);
+                recorder.key(key, pressed);
+            }
+
+            for _ in 0..4 {
+                vm.tick().unwrap();
+            }
+            vm.delay_timer();
+            vm.sound_timer();
+            recorder.end_frame();
+            last_v1.push(vm.reg()[1]);
+        }
+        let movie = recorder.finish();
+        // LD V1, K waits in frame 0 and takes whichever key is down after that
+        assert_eq!(last_v1, [0, 7, 7, 2, 2, 2]);
+
+        let mut replay = load();
+        assert!(movie.matches_rom(&replay));
+        replay.set_seed(movie.seed);
+        replay.set_quirks(movie.quirks);
+        let mut runner = Runner::new(replay, movie.ticks_per_frame, movie.events.clone());
+        runner.run_frames(movie.frames).unwrap();
+
+        assert_eq!(runner.vm.reg()[1], 2);
+        assert_eq!(runner.vm.reg(), vm.reg());
+        assert_eq!(runner.vm.pc(), vm.pc());
+        assert_eq!(runner.vm.save_state(), vm.save_state());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Movie::parse("chip8-movie 2\n").unwrap_err();
+        assert_eq!(err.line, 1);
+
+        let err = Movie::parse("chip8-movie 1\nquirks vf_reset bogus\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("bogus"));
+
+        let err = Movie::parse("chip8-movie 1\nrom abc\n").unwrap_err();
+        assert!(err.message.contains("seed"));
+    }
+}
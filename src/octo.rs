@@ -0,0 +1,1094 @@
+mod calc;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::path::Path;
+
+use crate::debugger::Monitor;
+use crate::vm::MEM_SIZE;
+use crate::vm::instruction::Instruction;
+
+const ROM_START: usize = 0x200;
+// macros expanding into themselves would never finish
+const MAX_EXPANSIONS: usize = 10_000;
+
+// statements, operators and operand words, none of them can name anything
+const KEYWORDS: &str = "
+    : := += -= =- |= &= ^= >>= <<= == != < > <= >= ; -
+    return clear bcd save load saveflags loadflags sprite jump jump0 hires lores exit plane
+    if then begin else end loop again while key -key i random delay buzzer hex bighex long";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, self.line, self.col, self.message
+            )
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+// Octo tokens are separated by whitespace, `#` starts a comment
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    col: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    // `:breakpoint name`, at the address of the instruction that follows
+    pub breakpoints: Vec<(String, u16)>,
+    pub monitors: Vec<Monitor>,
+}
+
+pub fn is_octo_source(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "8o")
+}
+
+pub fn compile_file(path: &Path) -> Result<Program, CompileError> {
+    let file = path.display().to_string();
+    let source = std::fs::read_to_string(path).map_err(|err| CompileError {
+        file: file.clone(),
+        line: 0,
+        col: 0,
+        message: err.to_string(),
+    })?;
+
+    Compiler::new(file, &source).run()
+}
+
+pub fn compile(source: &str) -> Result<Program, CompileError> {
+    Compiler::new("<source>".to_string(), source).run()
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut start = None;
+
+        for (pos, ch) in line.char_indices().chain([(line.len(), ' ')]) {
+            match (ch.is_whitespace(), start) {
+                (false, None) => start = Some(pos),
+                (true, Some(begin)) => {
+                    tokens.push(Token {
+                        text: line[begin..pos].to_string(),
+                        line: idx + 1,
+                        col: line[..begin].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    tokens
+}
+
+// decimal, `0x` hex and `0b` binary, optionally negative
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let val = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()? as f64
+    } else if digits.starts_with(|ch: char| ch.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -val } else { val })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fixup {
+    // low 12 bits of the opcode
+    Addr,
+    // the word after F000
+    Long,
+    // the two bytes of `:unpack`, with the nibble that goes on top
+    Unpack(u8),
+}
+
+// a use of a label that wasn't defined yet
+#[derive(Debug)]
+struct Pending {
+    addr: usize,
+    kind: Fixup,
+    token: Token,
+}
+
+#[derive(Debug)]
+enum Block {
+    // address of the jump over the `begin` branch
+    If(usize),
+    // address of the jump over the `else` branch
+    Else(usize),
+    Loop { start: usize, whiles: Vec<usize> },
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+// what a condition compiles to, see `Compiler::condition`
+struct Test {
+    setup: Vec<Instruction>,
+    skip_unless: Instruction,
+    skip_if: Instruction,
+}
+
+struct Compiler {
+    file: String,
+    tokens: VecDeque<Token>,
+    last: Option<Token>,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    pending: Vec<Pending>,
+    blocks: Vec<(Block, Token)>,
+    // 0x200 holds `jump main` unless main comes first
+    jump_main: bool,
+    expansions: usize,
+    breakpoints: Vec<(String, u16)>,
+    monitors: Vec<Monitor>,
+}
+
+impl Compiler {
+    fn new(file: String, source: &str) -> Self {
+        Self {
+            file,
+            tokens: tokenize(source).into(),
+            last: None,
+            // the placeholder for `jump main`
+            rom: vec![0x10, 0x00],
+            here: ROM_START + 2,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            pending: Vec::new(),
+            blocks: Vec::new(),
+            jump_main: true,
+            expansions: 0,
+            breakpoints: Vec::new(),
+            monitors: Vec::new(),
+        }
+    }
+
+    fn error(&self, tok: &Token, message: impl Into<String>) -> CompileError {
+        CompileError {
+            file: self.file.clone(),
+            line: tok.line,
+            col: tok.col,
+            message: message.into(),
+        }
+    }
+
+    fn next(&mut self, what: &str) -> Result<Token, CompileError> {
+        match self.tokens.pop_front() {
+            Some(tok) => {
+                self.last = Some(tok.clone());
+                Ok(tok)
+            }
+            None => Err(match &self.last {
+                Some(last) => CompileError {
+                    col: last.col + last.text.chars().count(),
+                    ..self.error(last, format!("expected {}", what))
+                },
+                None => self.error(
+                    &Token {
+                        text: String::new(),
+                        line: 1,
+                        col: 1,
+                    },
+                    format!("expected {}", what),
+                ),
+            }),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, CompileError> {
+        let tok = self.next(&format!("`{}`", text))?;
+        if tok.text != text {
+            return Err(self.error(&tok, format!("expected `{}`, got `{}`", text, tok.text)));
+        }
+
+        Ok(tok)
+    }
+
+    fn run(mut self) -> Result<Program, CompileError> {
+        while !self.tokens.is_empty() {
+            let tok = self.next("a statement")?;
+            self.statement(tok)?;
+        }
+
+        self.finish()
+    }
+
+    fn finish(mut self) -> Result<Program, CompileError> {
+        if let Some((block, tok)) = self.blocks.last() {
+            let missing = match block {
+                Block::If(_) | Block::Else(_) => "end",
+                Block::Loop { .. } => "again",
+            };
+            return Err(self.error(tok, format!("`{}` is missing its `{}`", tok.text, missing)));
+        }
+
+        if self.jump_main {
+            let Some(&main) = self.labels.get("main") else {
+                return Err(CompileError {
+                    file: self.file.clone(),
+                    line: 0,
+                    col: 0,
+                    message: "there is no `main` label".to_string(),
+                });
+            };
+            self.patch(ROM_START, Fixup::Addr, main as u16);
+        }
+
+        for pending in std::mem::take(&mut self.pending) {
+            let Some(&addr) = self.labels.get(&pending.token.text) else {
+                return Err(self.error(
+                    &pending.token,
+                    format!("undefined label `{}`", pending.token.text),
+                ));
+            };
+
+            let max = match pending.kind {
+                Fixup::Long => 0xffff,
+                Fixup::Addr | Fixup::Unpack(_) => 0xfff,
+            };
+            if addr > max {
+                return Err(self.error(
+                    &pending.token,
+                    format!("`{}` at 0x{:x} is out of reach", pending.token.text, addr),
+                ));
+            }
+
+            self.patch(pending.addr, pending.kind, addr as u16);
+        }
+
+        Ok(Program {
+            rom: self.rom,
+            labels: self
+                .labels
+                .into_iter()
+                .map(|(name, addr)| (name, addr as u16))
+                .collect(),
+            breakpoints: self.breakpoints,
+            monitors: self.monitors,
+        })
+    }
+
+    fn patch(&mut self, addr: usize, kind: Fixup, val: u16) {
+        let idx = addr - ROM_START;
+        match kind {
+            Fixup::Addr => {
+                self.rom[idx] = (self.rom[idx] & 0xf0) | (val >> 8) as u8;
+                self.rom[idx + 1] = val as u8;
+            }
+            Fixup::Long => self.rom[idx + 2..idx + 4].copy_from_slice(&val.to_be_bytes()),
+            Fixup::Unpack(nibble) => {
+                self.rom[idx + 1] = (nibble << 4) | (val >> 8) as u8;
+                self.rom[idx + 3] = val as u8;
+            }
+        }
+    }
+
+    fn emit_byte(&mut self, tok: &Token, byte: u8) -> Result<(), CompileError> {
+        if self.here >= MEM_SIZE {
+            return Err(self.error(tok, "program doesn't fit in memory"));
+        }
+
+        let idx = self.here - ROM_START;
+        if self.rom.len() <= idx {
+            self.rom.resize(idx + 1, 0);
+        }
+        self.rom[idx] = byte;
+        self.here += 1;
+
+        Ok(())
+    }
+
+    fn emit(&mut self, tok: &Token, ins: Instruction) -> Result<(), CompileError> {
+        for byte in ins.encode().to_be_bytes() {
+            self.emit_byte(tok, byte)?;
+        }
+
+        Ok(())
+    }
+
+    fn reg(&self, tok: &Token) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(&tok.text) {
+            return Some(reg);
+        }
+
+        let idx = tok.text.strip_prefix(['v', 'V'])?;
+        if idx.len() != 1 {
+            return None;
+        }
+
+        u8::from_str_radix(idx, 16).ok()
+    }
+
+    fn expect_reg(&mut self) -> Result<u8, CompileError> {
+        let tok = self.next("a register")?;
+        self.reg(&tok)
+            .ok_or_else(|| self.error(&tok, format!("expected a register, got `{}`", tok.text)))
+    }
+
+    // a literal, a constant or a label that is already defined
+    fn known(&self, tok: &Token) -> Option<f64> {
+        parse_number(&tok.text)
+            .or_else(|| self.constants.get(&tok.text).copied())
+            .or_else(|| self.labels.get(&tok.text).map(|&addr| addr as f64))
+    }
+
+    fn value_in(&self, tok: &Token, min: i64, max: i64) -> Result<i64, CompileError> {
+        let Some(val) = self.known(tok) else {
+            return Err(self.error(tok, format!("undefined name `{}`", tok.text)));
+        };
+
+        let val = val.floor();
+        if !(min as f64..=max as f64).contains(&val) {
+            return Err(self.error(
+                tok,
+                format!("`{}` is out of range {}..={}", tok.text, min, max),
+            ));
+        }
+
+        Ok(val as i64)
+    }
+
+    fn byte(&mut self) -> Result<u8, CompileError> {
+        let tok = self.next("a value")?;
+        Ok(self.value_in(&tok, -0x80, 0xff)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, CompileError> {
+        let tok = self.next("a value")?;
+        Ok(self.value_in(&tok, 0, 0x0f)? as u8)
+    }
+
+    // labels may be used before they are defined, they are patched in `finish`
+    fn target(&mut self, kind: Fixup) -> Result<u16, CompileError> {
+        let tok = self.next("an address")?;
+        let max = match kind {
+            Fixup::Long => 0xffff,
+            Fixup::Addr | Fixup::Unpack(_) => 0xfff,
+        };
+
+        if self.known(&tok).is_some() {
+            return Ok(self.value_in(&tok, 0, max)? as u16);
+        }
+
+        self.check_name(&tok)?;
+        self.pending.push(Pending {
+            addr: self.here,
+            kind,
+            token: tok,
+        });
+
+        Ok(0)
+    }
+
+    fn check_name(&self, tok: &Token) -> Result<(), CompileError> {
+        let reserved = KEYWORDS.split_whitespace().any(|word| word == tok.text)
+            || tok.text.starts_with(':')
+            || parse_number(&tok.text).is_some()
+            || self.reg(tok).is_some();
+
+        if reserved {
+            return Err(self.error(tok, format!("`{}` can't be used as a name", tok.text)));
+        }
+
+        Ok(())
+    }
+
+    fn define(&self, tok: &Token) -> Result<(), CompileError> {
+        self.check_name(tok)?;
+
+        let name = tok.text.as_str();
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.macros.contains_key(name)
+        {
+            return Err(self.error(tok, format!("`{}` is already defined", name)));
+        }
+
+        Ok(())
+    }
+
+    // the tokens up to the matching `}`, and the `}` itself
+    fn braced(&mut self) -> Result<(Vec<Token>, Token), CompileError> {
+        self.expect("{")?;
+
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let tok = self.next("`}`")?;
+            match tok.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok((body, tok)),
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(tok);
+        }
+    }
+
+    fn calc(&mut self) -> Result<f64, CompileError> {
+        let (body, end) = self.braced()?;
+
+        let here = self.here as f64;
+        let lookup = |name: &str| match name {
+            "HERE" => Some(here),
+            "PI" => Some(std::f64::consts::PI),
+            "E" => Some(std::f64::consts::E),
+            _ => self
+                .constants
+                .get(name)
+                .copied()
+                .or_else(|| self.labels.get(name).map(|&addr| addr as f64)),
+        };
+
+        let val = calc::eval(&body, &end, &lookup).map_err(|(tok, msg)| self.error(&tok, msg))?;
+        if !val.is_finite() {
+            return Err(self.error(&end, "the result isn't a finite number"));
+        }
+
+        Ok(val)
+    }
+
+    fn statement(&mut self, tok: Token) -> Result<(), CompileError> {
+        use Instruction::*;
+
+        let ins = match tok.text.as_str() {
+            ":" => {
+                let name = self.next("a label name")?;
+                return self.label(name);
+            }
+            ":const" => {
+                let name = self.next("a constant name")?;
+                self.define(&name)?;
+                let val = self.next("a value")?;
+                let Some(val) = self.known(&val) else {
+                    return Err(self.error(&val, format!("undefined name `{}`", val.text)));
+                };
+                self.constants.insert(name.text, val);
+                return Ok(());
+            }
+            ":calc" => {
+                let name = self.next("a constant name")?;
+                self.define(&name)?;
+                let val = self.calc()?;
+                self.constants.insert(name.text, val);
+                return Ok(());
+            }
+            ":alias" => {
+                let name = self.next("an alias name")?;
+                self.define(&name)?;
+                let reg = self.expect_reg()?;
+                self.aliases.insert(name.text, reg);
+                return Ok(());
+            }
+            ":macro" => return self.define_macro(),
+            ":byte" => {
+                let byte = if self.tokens.front().is_some_and(|next| next.text == "{") {
+                    let val = self.calc()?.floor();
+                    if !(-128.0..=255.0).contains(&val) {
+                        return Err(self.error(&tok, format!("{} doesn't fit in a byte", val)));
+                    }
+                    val as i64 as u8
+                } else {
+                    self.byte()?
+                };
+                return self.emit_byte(&tok, byte);
+            }
+            ":org" => {
+                let addr = self.next("an address")?;
+                self.here = self.value_in(&addr, ROM_START as i64, MEM_SIZE as i64 - 1)? as usize;
+                return Ok(());
+            }
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let addr = self.target(Fixup::Unpack(nibble))?;
+                self.emit(
+                    &tok,
+                    LdImm {
+                        x: 0,
+                        kk: (nibble << 4) | (addr >> 8) as u8,
+                    },
+                )?;
+                LdImm {
+                    x: 1,
+                    kk: addr as u8,
+                }
+            }
+            ":call" => Call(self.target(Fixup::Addr)?),
+            ":breakpoint" => {
+                let name = self.next("a breakpoint name")?;
+                self.breakpoints.push((name.text, self.here as u16));
+                return Ok(());
+            }
+            ":monitor" => {
+                let name = self.next("an address")?;
+                let addr = self.value_in(&name, 0, MEM_SIZE as i64 - 1)? as u16;
+                let len = self.next("a length")?;
+                let len = self.value_in(&len, 1, 0x100)? as u16;
+                self.monitors.push(Monitor {
+                    name: name.text,
+                    addr,
+                    len,
+                });
+                return Ok(());
+            }
+            // forward declarations of old Octo versions, there's nothing to do
+            ":proto" => {
+                self.next("a label name")?;
+                return Ok(());
+            }
+            ";" | "return" => Ret,
+            "clear" => Cls,
+            "bcd" => LdB(self.expect_reg()?),
+            "save" | "load" => {
+                let x = self.expect_reg()?;
+                let range = self.tokens.front().is_some_and(|next| next.text == "-");
+                let save = tok.text == "save";
+                match (save, range) {
+                    (true, false) => LdIVx(x),
+                    (false, false) => LdVxI(x),
+                    (_, true) => {
+                        self.next("`-`")?;
+                        let y = self.expect_reg()?;
+                        if save { Save { x, y } } else { Load { x, y } }
+                    }
+                }
+            }
+            "saveflags" => LdRVx(self.expect_reg()?),
+            "loadflags" => LdVxR(self.expect_reg()?),
+            "sprite" => Drw {
+                x: self.expect_reg()?,
+                y: self.expect_reg()?,
+                n: self.nibble()?,
+            },
+            "jump" => Jp(self.target(Fixup::Addr)?),
+            "jump0" => {
+                let addr = self.target(Fixup::Addr)?;
+                JpV0 {
+                    x: (addr >> 8) as u8,
+                    addr,
+                }
+            }
+            "hires" => High,
+            "lores" => Low,
+            "exit" => Exit,
+            "scroll-down" => Scd(self.nibble()?),
+            "scroll-up" => Scu(self.nibble()?),
+            "scroll-right" => Scr,
+            "scroll-left" => Scl,
+            "plane" => {
+                let n = self.next("a plane mask")?;
+                Plane(self.value_in(&n, 0, 3)? as u8)
+            }
+            "if" => return self.if_statement(tok),
+            "else" => {
+                let Some((Block::If(jump), _)) = self.blocks.pop() else {
+                    return Err(self.error(&tok, "`else` without `begin`"));
+                };
+
+                let over = self.here;
+                self.emit(&tok, Jp(0))?;
+                self.patch(jump, Fixup::Addr, self.here as u16);
+                self.blocks.push((Block::Else(over), tok));
+                return Ok(());
+            }
+            "end" => {
+                let Some((Block::If(jump) | Block::Else(jump), _)) = self.blocks.pop() else {
+                    return Err(self.error(&tok, "`end` without `begin`"));
+                };
+
+                self.patch(jump, Fixup::Addr, self.here as u16);
+                return Ok(());
+            }
+            "loop" => {
+                self.blocks.push((
+                    Block::Loop {
+                        start: self.here,
+                        whiles: Vec::new(),
+                    },
+                    tok,
+                ));
+                return Ok(());
+            }
+            "while" => {
+                let test = self.condition()?;
+                for ins in test.setup {
+                    self.emit(&tok, ins)?;
+                }
+                self.emit(&tok, test.skip_if)?;
+
+                let jump = self.here;
+                let Some((Block::Loop { whiles, .. }, _)) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|(block, _)| matches!(block, Block::Loop { .. }))
+                else {
+                    return Err(self.error(&tok, "`while` outside of a `loop`"));
+                };
+                whiles.push(jump);
+                Jp(0)
+            }
+            "again" => {
+                let Some((Block::Loop { start, whiles }, _)) = self.blocks.pop() else {
+                    return Err(self.error(&tok, "`again` without `loop`"));
+                };
+
+                self.emit(&tok, Jp(start as u16))?;
+                for jump in whiles {
+                    self.patch(jump, Fixup::Addr, self.here as u16);
+                }
+                return Ok(());
+            }
+            "i" => {
+                let op = self.next("`:=` or `+=`")?;
+                match op.text.as_str() {
+                    ":=" => return self.load_i(&tok),
+                    "+=" => AddI(self.expect_reg()?),
+                    _ => return Err(self.error(&op, format!("unexpected `{}` after `i`", op.text))),
+                }
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.expect_reg()?;
+                if tok.text == "delay" {
+                    LdDtVx(x)
+                } else {
+                    LdStVx(x)
+                }
+            }
+            "audio" | "pitch" | "native" | ":next" | ":stringmode" | ":assert" => {
+                return Err(self.error(&tok, format!("`{}` isn't supported", tok.text)));
+            }
+            _ => {
+                if let Some(x) = self.reg(&tok) {
+                    self.register_statement(x)?
+                } else if let Some(mac) = self.macros.get(&tok.text).cloned() {
+                    return self.expand(&tok, mac);
+                } else if self.constants.contains_key(&tok.text)
+                    || parse_number(&tok.text).is_some()
+                {
+                    // bare numbers are data
+                    let byte = self.value_in(&tok, -0x80, 0xff)? as u8;
+                    return self.emit_byte(&tok, byte);
+                } else {
+                    // a bare label calls it
+                    self.tokens.push_front(tok.clone());
+                    Call(self.target(Fixup::Addr)?)
+                }
+            }
+        };
+
+        self.emit(&tok, ins)
+    }
+
+    fn label(&mut self, name: Token) -> Result<(), CompileError> {
+        self.define(&name)?;
+
+        // main comes first, there's nothing to jump over
+        if name.text == "main"
+            && self.jump_main
+            && self.here == ROM_START + 2
+            && self.rom.len() == 2
+        {
+            self.rom.clear();
+            self.here = ROM_START;
+            self.jump_main = false;
+        }
+
+        self.labels.insert(name.text, self.here);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), CompileError> {
+        let name = self.next("a macro name")?;
+        self.define(&name)?;
+
+        let mut args = Vec::new();
+        while self.tokens.front().is_some_and(|next| next.text != "{") {
+            args.push(self.next("a macro argument")?.text);
+        }
+
+        let (body, _) = self.braced()?;
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    // the body replaces the invocation, with each argument substituted
+    fn expand(&mut self, tok: &Token, mac: Macro) -> Result<(), CompileError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(tok, format!("`{}` expands forever", tok.text)));
+        }
+
+        let mut args = Vec::new();
+        for _ in &mac.args {
+            args.push(self.next(&format!("an argument for `{}`", tok.text))?);
+        }
+
+        for body_tok in mac.body.iter().rev() {
+            let tok = match mac.args.iter().position(|arg| *arg == body_tok.text) {
+                Some(idx) => args[idx].clone(),
+                None => body_tok.clone(),
+            };
+            self.tokens.push_front(tok);
+        }
+
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<Instruction, CompileError> {
+        use Instruction::*;
+
+        let op = self.next("an operator")?;
+        let ins = match op.text.as_str() {
+            ":=" => {
+                let src = self.next("a value")?;
+                match src.text.as_str() {
+                    "random" => Rnd {
+                        x,
+                        kk: self.byte()?,
+                    },
+                    "key" => LdVxK(x),
+                    "delay" => LdVxDt(x),
+                    _ => match self.reg(&src) {
+                        Some(y) => LdReg { x, y },
+                        None => LdImm {
+                            x,
+                            kk: self.value_in(&src, -0x80, 0xff)? as u8,
+                        },
+                    },
+                }
+            }
+            "+=" | "-=" => {
+                let src = self.next("a value")?;
+                match (self.reg(&src), op.text.as_str()) {
+                    (Some(y), "+=") => AddReg { x, y },
+                    (Some(y), _) => Sub { x, y },
+                    (None, "+=") => AddImm {
+                        x,
+                        kk: self.value_in(&src, -0x80, 0xff)? as u8,
+                    },
+                    (None, _) => AddImm {
+                        x,
+                        kk: (self.value_in(&src, -0x80, 0xff)? as u8).wrapping_neg(),
+                    },
+                }
+            }
+            "=-" => Subn {
+                x,
+                y: self.expect_reg()?,
+            },
+            "|=" => Or {
+                x,
+                y: self.expect_reg()?,
+            },
+            "&=" => And {
+                x,
+                y: self.expect_reg()?,
+            },
+            "^=" => Xor {
+                x,
+                y: self.expect_reg()?,
+            },
+            ">>=" => Shr {
+                x,
+                y: self.expect_reg()?,
+            },
+            "<<=" => Shl {
+                x,
+                y: self.expect_reg()?,
+            },
+            _ => return Err(self.error(&op, format!("unknown operator `{}`", op.text))),
+        };
+
+        Ok(ins)
+    }
+
+    fn load_i(&mut self, tok: &Token) -> Result<(), CompileError> {
+        use Instruction::*;
+
+        let ins = match self.tokens.front().map(|next| next.text.as_str()) {
+            Some("hex") => {
+                self.next("`hex`")?;
+                LdF(self.expect_reg()?)
+            }
+            Some("bighex") => {
+                self.next("`bighex`")?;
+                LdHf(self.expect_reg()?)
+            }
+            Some("long") => {
+                self.next("`long`")?;
+                let addr = self.target(Fixup::Long)?;
+                self.emit(tok, LdILong)?;
+                for byte in addr.to_be_bytes() {
+                    self.emit_byte(tok, byte)?;
+                }
+                return Ok(());
+            }
+            _ => LdI(self.target(Fixup::Addr)?),
+        };
+
+        self.emit(tok, ins)
+    }
+
+    // Compiles `vx op y`. `skip_unless` skips the next instruction when the
+    // condition doesn't hold, for `then`. `skip_if` is its inverse, for the
+    // jumps of `begin` and `while`. Comparisons go through vf.
+    fn condition(&mut self) -> Result<Test, CompileError> {
+        use Instruction::*;
+
+        let x = self.expect_reg()?;
+        let op = self.next("a comparison")?;
+
+        match op.text.as_str() {
+            "key" => {
+                return Ok(Test {
+                    setup: Vec::new(),
+                    skip_unless: Sknp(x),
+                    skip_if: Skp(x),
+                });
+            }
+            "-key" => {
+                return Ok(Test {
+                    setup: Vec::new(),
+                    skip_unless: Skp(x),
+                    skip_if: Sknp(x),
+                });
+            }
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {}
+            _ => return Err(self.error(&op, format!("unknown comparison `{}`", op.text))),
+        }
+
+        let rhs = self.next("a value")?;
+        let y = self.reg(&rhs);
+
+        let (eq, ne) = match y {
+            Some(y) => (SeReg { x, y }, SneReg { x, y }),
+            None => {
+                let kk = self.value_in(&rhs, -0x80, 0xff)? as u8;
+                (SeImm { x, kk }, SneImm { x, kk })
+            }
+        };
+
+        match op.text.as_str() {
+            "==" => {
+                return Ok(Test {
+                    setup: Vec::new(),
+                    skip_unless: ne,
+                    skip_if: eq,
+                });
+            }
+            "!=" => {
+                return Ok(Test {
+                    setup: Vec::new(),
+                    skip_unless: eq,
+                    skip_if: ne,
+                });
+            }
+            _ => {}
+        }
+
+        if x == 0xf || y == Some(0xf) {
+            return Err(self.error(&op, "vf can't be compared with `<` and friends"));
+        }
+
+        // vf := y, then either vf = y - vx with vf = y >= vx, or
+        // vf = vx - y with vf = vx >= y
+        let load = match y {
+            Some(y) => LdReg { x: 0xf, y },
+            None => LdImm {
+                x: 0xf,
+                kk: self.value_in(&rhs, -0x80, 0xff)? as u8,
+            },
+        };
+        let (sub, flag) = match op.text.as_str() {
+            ">" => (Sub { x: 0xf, y: x }, 0),
+            "<=" => (Sub { x: 0xf, y: x }, 1),
+            "<" => (Subn { x: 0xf, y: x }, 0),
+            _ => (Subn { x: 0xf, y: x }, 1),
+        };
+
+        Ok(Test {
+            setup: vec![load, sub],
+            skip_unless: SneImm { x: 0xf, kk: flag },
+            skip_if: SeImm { x: 0xf, kk: flag },
+        })
+    }
+
+    fn if_statement(&mut self, tok: Token) -> Result<(), CompileError> {
+        let test = self.condition()?;
+        let word = self.next("`then` or `begin`")?;
+
+        for ins in test.setup {
+            self.emit(&tok, ins)?;
+        }
+
+        match word.text.as_str() {
+            "then" => self.emit(&tok, test.skip_unless),
+            "begin" => {
+                self.emit(&tok, test.skip_if)?;
+                let jump = self.here;
+                self.emit(&tok, Instruction::Jp(0))?;
+                self.blocks.push((Block::If(jump), tok));
+                Ok(())
+            }
+            _ => Err(self.error(
+                &word,
+                format!("expected `then` or `begin`, got `{}`", word.text),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    #[test]
+    fn test_compile() {
+        let program = compile(
+            "
+            :const SPEED 2
+            :alias x v3
+            :macro twice op { op op }
+            :calc HALF { SPEED / 2 }
+
+            : data 0xff 0x81
+            : main
+                x := HALF
+                twice clear
+                loop
+                    x += SPEED
+                    while x != 7
+                    :breakpoint moved
+                    if x key begin jump main else i := data end
+                again
+                i := long data
+                :monitor data 2
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.labels["main"], 0x204);
+        assert_eq!(program.breakpoints, [("moved".to_string(), 0x210)]);
+        assert_eq!(
+            program.monitors,
+            [Monitor {
+                name: "data".to_string(),
+                addr: 0x202,
+                len: 2,
+            }]
+        );
+        assert_eq!(
+            program.rom,
+            [
+                0x12, 0x04, // jump main
+                0xff, 0x81, // data
+                0x63, 0x01, // x := HALF
+                0x00, 0xe0, 0x00, 0xe0, // twice clear
+                0x73, 0x02, // x += SPEED
+                0x43, 0x07, 0x12, 0x1c, // while x != 7
+                0xe3, 0x9e, 0x12, 0x18, // if x key begin
+                0x12, 0x04, // jump main
+                0x12, 0x1a, // else
+                0xa2, 0x02, // i := data, end
+                0x12, 0x0a, // again
+                0xf0, 0x00, 0x02, 0x02, // i := long data
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        for (a, b) in [(1, 2), (2, 2), (3, 2), (0, 255)] {
+            for (op, expected) in [
+                ("==", a == b),
+                ("!=", a != b),
+                ("<", a < b),
+                (">", a > b),
+                ("<=", a <= b),
+                (">=", a >= b),
+            ] {
+                let source = format!(
+                    ": main
+                        v0 := {a} v1 := {b}
+                        if v0 {op} v1 then v2 := 1
+                        if v0 {op} {b} begin v3 := 1 else v3 := 2 end
+                        loop again"
+                );
+                let program = compile(&source).unwrap();
+
+                let mut vm = Vm::new();
+                vm.load_rom_bytes("test.8o".to_string(), &program.rom)
+                    .unwrap();
+                for _ in 0..20 {
+                    vm.tick().unwrap();
+                }
+
+                let reg = vm.reg();
+                assert_eq!(reg[2] == 1, expected, "{} {} {}", a, op, b);
+                assert_eq!(reg[3], if expected { 1 } else { 2 }, "{} {} {}", a, op, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = compile(": main\n  v0 := 256").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 9));
+
+        let err = compile(": main\n  jump nowhere").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 8));
+        assert!(err.message.contains("nowhere"));
+
+        let err = compile(": main\n  loop\n  v0 += 1").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 3));
+
+        let err = compile("clear").unwrap_err();
+        assert_eq!(err.line, 0);
+    }
+}
@@ -0,0 +1,129 @@
+use super::{Token, parse_number};
+
+const BINARY: [&str; 19] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", ">", ">=",
+    "==", "!=",
+];
+
+const UNARY: [&str; 13] = [
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor",
+];
+
+// Evaluates the tokens between the braces of `:calc name { ... }`. Like Octo
+// there is no precedence, binary operators group from the right so
+// `2 * 3 + 1` is 8. `end` is the closing brace, for errors about missing values.
+pub fn eval(
+    tokens: &[Token],
+    end: &Token,
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, (Token, String)> {
+    let mut calc = Calc {
+        tokens,
+        pos: 0,
+        end,
+        lookup,
+    };
+
+    let val = calc.expr()?;
+    if let Some(tok) = tokens.get(calc.pos) {
+        return Err((tok.clone(), format!("unexpected `{}`", tok.text)));
+    }
+
+    Ok(val)
+}
+
+struct Calc<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    end: &'a Token,
+    lookup: &'a dyn Fn(&str) -> Option<f64>,
+}
+
+impl Calc<'_> {
+    fn next(&mut self) -> Result<&Token, (Token, String)> {
+        let tok = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| (self.end.clone(), "expected a value".to_string()))?;
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn expr(&mut self) -> Result<f64, (Token, String)> {
+        let lhs = self.term()?;
+
+        let Some(op) = self.tokens.get(self.pos) else {
+            return Ok(lhs);
+        };
+        if !BINARY.contains(&op.text.as_str()) {
+            return Ok(lhs);
+        }
+        let op = op.text.clone();
+        self.pos += 1;
+
+        let rhs = self.expr()?;
+        let (a, b) = (lhs as i64, rhs as i64);
+        let val = match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.wrapping_shl(b as u32) as f64,
+            ">>" => a.wrapping_shr(b as u32) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => (lhs < rhs) as u8 as f64,
+            "<=" => (lhs <= rhs) as u8 as f64,
+            ">" => (lhs > rhs) as u8 as f64,
+            ">=" => (lhs >= rhs) as u8 as f64,
+            "==" => (lhs == rhs) as u8 as f64,
+            _ => (lhs != rhs) as u8 as f64,
+        };
+
+        Ok(val)
+    }
+
+    fn term(&mut self) -> Result<f64, (Token, String)> {
+        let tok = self.next()?.clone();
+
+        if tok.text == "(" {
+            let val = self.expr()?;
+            let close = self.next()?;
+            if close.text != ")" {
+                return Err((close.clone(), "expected `)`".to_string()));
+            }
+            return Ok(val);
+        }
+
+        if UNARY.contains(&tok.text.as_str()) {
+            let val = self.term()?;
+            let val = match tok.text.as_str() {
+                "-" => -val,
+                "~" => !(val as i64) as f64,
+                "!" => (val == 0.0) as u8 as f64,
+                "sin" => val.sin(),
+                "cos" => val.cos(),
+                "tan" => val.tan(),
+                "exp" => val.exp(),
+                "log" => val.ln(),
+                "abs" => val.abs(),
+                "sqrt" => val.sqrt(),
+                // f64::signum is 1 for 0
+                "sign" if val == 0.0 => 0.0,
+                "sign" => val.signum(),
+                "ceil" => val.ceil(),
+                _ => val.floor(),
+            };
+            return Ok(val);
+        }
+
+        parse_number(&tok.text)
+            .or_else(|| (self.lookup)(&tok.text))
+            .ok_or_else(|| (tok.clone(), format!("undefined name `{}`", tok.text)))
+    }
+}
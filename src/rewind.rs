@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use crate::vm::Vm;
+
+const FRAMES_PER_SECOND: usize = 60;
+
+// Ring buffer of per-frame save states. Only the latest snapshot is kept whole,
+// every older frame is stored as a run-length encoded XOR delta that turns
+// the snapshot after it back into itself.
+#[derive(Debug, Default)]
+pub struct Rewind {
+    capacity: usize,
+    current: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    pub fn new(seconds: u32) -> Self {
+        Self {
+            capacity: seconds as usize * FRAMES_PER_SECOND,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_seconds(&mut self, seconds: u32) {
+        self.capacity = seconds as usize * FRAMES_PER_SECOND;
+        while self.deltas.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.deltas.clear();
+    }
+
+    // call once per emulated frame
+    pub fn push(&mut self, vm: &Vm) {
+        if self.capacity == 0 {
+            self.clear();
+            return;
+        }
+
+        let snapshot = vm.save_state();
+        if self.current.len() == snapshot.len() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas
+                .push_back(encode_delta(&self.current, &snapshot));
+        } else {
+            // a different state layout can't be diffed, start over
+            self.deltas.clear();
+        }
+
+        self.current = snapshot;
+    }
+
+    // restores the previous frame into the VM, false once the history is exhausted
+    pub fn step_back(&mut self, vm: &mut Vm) -> bool {
+        let Some(delta) = self.deltas.pop_back() else {
+            return false;
+        };
+
+        apply_delta(&mut self.current, &delta);
+        vm.load_state(&self.current).is_ok()
+    }
+}
+
+// Delta format: repeated (zero run varint, literal length varint, literal XOR bytes)
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < from.len() {
+        let zeros_start = pos;
+        while pos < from.len() && from[pos] == to[pos] {
+            pos += 1;
+        }
+
+        let literal_start = pos;
+        while pos < from.len() && from[pos] != to[pos] {
+            pos += 1;
+        }
+
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, pos - literal_start);
+        out.extend((literal_start..pos).map(|idx| from[idx] ^ to[idx]));
+    }
+
+    out
+}
+
+fn apply_delta(buf: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut idx = 0;
+
+    while idx < delta.len() {
+        pos += read_varint(delta, &mut idx);
+        let len = read_varint(delta, &mut idx);
+
+        for (dst, src) in buf[pos..pos + len].iter_mut().zip(&delta[idx..idx + len]) {
+            *dst ^= src;
+        }
+
+        pos += len;
+        idx += len;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &[u8], idx: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*idx];
+        *idx += 1;
+        val |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let from: Vec<u8> = (0..1000).map(|idx| (idx % 251) as u8).collect();
+        let mut to = from.clone();
+        to[0] ^= 0xff;
+        to[500..700].fill(7);
+        to[999] = 0;
+
+        let delta = encode_delta(&from, &to);
+        assert!(delta.len() < from.len());
+
+        let mut buf = to.clone();
+        apply_delta(&mut buf, &delta);
+        assert_eq!(buf, from);
+    }
+}
@@ -0,0 +1,162 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::settings::config::{self, Config};
+use crate::vm::{Quirks, QuirksPreset};
+
+// What's known about a ROM, from romdb.toml. Speed, quirks and colours are
+// applied when it's loaded, below a profile saved for it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+    pub sha1: String,
+    pub title: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub platform: QuirksPreset,
+    pub quirks: Option<Quirks>,
+    pub tickrate: Option<u32>,
+    #[serde(default)]
+    pub colors: Vec<String>,
+    pub keys: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Db {
+    #[serde(default)]
+    rom: Vec<RomInfo>,
+}
+
+lazy_static! {
+    // checked by the tests, a broken entry never gets this far
+    static ref ROMS: Vec<RomInfo> = parse(include_str!("romdb.toml")).unwrap();
+}
+
+fn parse(text: &str) -> Result<Vec<RomInfo>, String> {
+    let db: Db = toml::from_str(text).map_err(|err| err.to_string())?;
+    for info in &db.rom {
+        info.colors()?;
+    }
+
+    Ok(db.rom)
+}
+
+pub fn lookup(sha1: &str) -> Option<&'static RomInfo> {
+    find(&ROMS, sha1)
+}
+
+fn find<'a>(roms: &'a [RomInfo], sha1: &str) -> Option<&'a RomInfo> {
+    roms.iter()
+        .find(|info| info.sha1.eq_ignore_ascii_case(sha1))
+}
+
+impl RomInfo {
+    pub fn quirks(&self) -> Quirks {
+        self.quirks.unwrap_or_else(|| self.platform.quirks())
+    }
+
+    // background, foreground, plane 2 and overlap, as far as they're given
+    fn colors(&self) -> Result<Vec<[f32; 4]>, String> {
+        if self.colors.len() > 4 {
+            return Err(format!("{}: more than 4 colours", self.title));
+        }
+
+        self.colors
+            .iter()
+            .map(|color| {
+                config::parse_color(color).map_err(|err| format!("{}: {}", self.title, err))
+            })
+            .collect()
+    }
+
+    pub fn config(&self) -> Config {
+        let colors = self.colors().unwrap_or_default();
+
+        Config {
+            bg_color: colors.first().copied(),
+            fg_color: colors.get(1).copied(),
+            plane2_color: colors.get(2).copied(),
+            overlap_color: colors.get(3).copied(),
+            ticks_per_frame: self.tickrate.filter(|&ticks| ticks > 0),
+            quirks: Some(self.quirks()),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_db() {
+        let mut hashes = HashSet::new();
+        for info in ROMS.iter() {
+            assert!(
+                info.sha1.len() == 40 && info.sha1.bytes().all(|b| b.is_ascii_hexdigit()),
+                "{}: `{}` isn't a SHA-1",
+                info.title,
+                info.sha1
+            );
+            assert!(
+                hashes.insert(info.sha1.to_lowercase()),
+                "{} twice",
+                info.sha1
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let roms = parse(
+            "[[rom]]\n\
+             sha1 = \"0123456789abcdef0123456789abcdef01234567\"\n\
+             title = \"Game\"\n\
+             authors = [\"Someone\"]\n\
+             platform = \"cosmac-vip\"\n\
+             keys = \"5 to jump\"\n",
+        )
+        .unwrap();
+        let info = find(&roms, "0123456789ABCDEF0123456789ABCDEF01234567").unwrap();
+        assert_eq!(info.title, "Game");
+        assert_eq!(info.authors, ["Someone"]);
+        assert_eq!(info.keys.as_deref(), Some("5 to jump"));
+        assert!(find(&roms, "0000000000000000000000000000000000000000").is_none());
+
+        // the platform's quirks, nothing else
+        let config = info.config();
+        assert_eq!(config.quirks, Some(QuirksPreset::CosmacVip.quirks()));
+        assert_eq!(config.ticks_per_frame, None);
+        assert_eq!(config.fg_color, None);
+
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_config() {
+        let roms = parse(
+            "[[rom]]\n\
+             sha1 = \"00\"\n\
+             title = \"Game\"\n\
+             platform = \"schip\"\n\
+             tickrate = 30\n\
+             colors = [\"#000000\", \"#ff0000\"]\n",
+        )
+        .unwrap();
+        let config = roms[0].config();
+        assert_eq!(config.quirks, Some(QuirksPreset::Schip.quirks()));
+        assert_eq!(config.ticks_per_frame, Some(30));
+        assert_eq!(config.fg_color, Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(config.plane2_color, None);
+
+        assert!(parse("[[rom]]\nsha1 = \"00\"\ntitle = \"x\"\nplatform = \"nes\"\n").is_err());
+        assert!(
+            parse(
+                "[[rom]]\nsha1 = \"00\"\ntitle = \"x\"\nplatform = \"schip\"\ncolors = [\"red\"]\n"
+            )
+            .is_err()
+        );
+    }
+}
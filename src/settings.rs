@@ -1,18 +1,60 @@
+pub mod config;
+
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "gui")]
+use winit::keyboard::KeyCode;
+
+#[cfg(feature = "gui")]
+use crate::app::keymap::KEYMAP;
+use crate::romdb::RomInfo;
+use crate::vm::{Quirks, QuirksPreset, Vm};
+use config::{Config, ConfigError};
+
+// what the settings window's sliders go to, the CLI and config file agree
+pub const BEEP_FREQ_RANGE: RangeInclusive<f32> = 55.0..=880.0;
+pub const SEPIA_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
 #[derive(Debug)]
 pub struct Settings {
     pub show_settings: bool,
+    pub show_debugger: bool,
 
     pub fg_color: [f32; 4],
     pub bg_color: [f32; 4],
+    pub plane2_color: [f32; 4],
+    pub overlap_color: [f32; 4],
     pub window_has_shadow: bool,
+    pub borderless: bool,
 
     pub ticks_per_frame: u32,
+    pub quirks: Quirks,
+    pub rewind_seconds: u32,
 
     pub beep_freq: f32,
     pub scale_mode: bool,
 
     pub pp_enabled: bool,
     pub sepia_amount: f32,
+
+    // only the window has a keyboard
+    #[cfg(feature = "gui")]
+    pub keymap: [KeyCode; 16],
+
+    // where `save` writes, nothing is saved without it
+    path: Option<PathBuf>,
+    // the config file as last read or written, and as it was loaded
+    file: Config,
+    loaded: Config,
+    // settings this run got from elsewhere, the command line or a movie.
+    // `save` leaves them as they are in the file
+    pinned: Config,
+    rom_info: Option<&'static RomInfo>,
+    // where the loaded ROM's profile is or would go, and what's in it
+    profile_path: Option<PathBuf>,
+    profile: Option<Config>,
 }
 
 impl Settings {
@@ -20,16 +62,458 @@ impl Settings {
     pub fn new() -> Self {
         Self {
             show_settings: false,
+            show_debugger: false,
             fg_color: [1.0, 0.0, 0.514, 1.0],
             bg_color: [0.024, 0.024, 0.024, 1.0],
+            plane2_color: [0.0, 0.745, 0.855, 1.0],
+            overlap_color: [1.0, 0.933, 0.6, 1.0],
             window_has_shadow: true,
+            borderless: false,
 
             ticks_per_frame: 10,
+            quirks: Quirks::default(),
+            rewind_seconds: 60,
             beep_freq: 220.0,
             scale_mode: true,
 
             pp_enabled: true,
             sepia_amount: 0.5,
+
+            #[cfg(feature = "gui")]
+            keymap: KEYMAP,
+
+            path: None,
+            file: Config::default(),
+            loaded: Config::default(),
+            pinned: Config::default(),
+            rom_info: None,
+            profile_path: None,
+            profile: None,
+        }
+    }
+
+    // The defaults with the config file at `path` on top, saved back there.
+    // Problems are only reported, a file that isn't TOML is left alone and
+    // nothing gets saved over it.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut settings = Settings::new();
+        let Some(path) = path else {
+            return settings;
+        };
+
+        match read_config(&path) {
+            Ok(config) => {
+                let config = config.unwrap_or_default();
+                config.apply(&mut settings);
+                settings.loaded = config.clone();
+                settings.file = config;
+                settings.path = Some(path);
+            }
+            Err(err) => eprintln!("{}: {}, settings won't be saved", path.display(), err),
+        }
+
+        settings
+    }
+
+    // for this run only, the config file doesn't get them
+    pub fn pin(&mut self, config: &Config) {
+        config.apply(self);
+        self.pinned.merge(config);
+    }
+
+    // What the built-in database has for the loaded ROM, then the profile
+    // saved for it. Both are pinned and win over the config file, pin the
+    // command line after this.
+    pub fn load_rom(&mut self, vm: &Vm) {
+        self.rom_info = vm.rom_info();
+        if let Some(info) = self.rom_info {
+            self.pin(&info.config());
+        }
+
+        if let Some(hash) = vm.rom_hash() {
+            self.load_profile(hash);
+        }
+    }
+
+    pub fn rom_info(&self) -> Option<&'static RomInfo> {
+        self.rom_info
+    }
+
+    // in `roms/<sha1 of the ROM>.toml` next to the config file
+    fn load_profile(&mut self, rom_hash: &str) {
+        let Some(dir) = self.path.as_deref().and_then(Path::parent) else {
+            return;
+        };
+        let path = dir.join("roms").join(format!("{}.toml", rom_hash));
+
+        match read_config(&path) {
+            Ok(profile) => {
+                if let Some(profile) = &profile {
+                    self.pin(profile);
+                }
+                self.profile = profile;
+                self.profile_path = Some(path);
+            }
+            Err(err) => eprintln!("{}: {}, it won't be saved", path.display(), err),
+        }
+    }
+
+    // whether there is a ROM to save a profile for
+    pub fn can_save_profile(&self) -> bool {
+        self.profile_path.is_some()
+    }
+
+    pub fn has_profile(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    pub fn save(&mut self) -> Result<(), ConfigError> {
+        let mut changed = Config::capture(self);
+        changed.unset(&self.pinned);
+        let mut file = self.file.clone();
+        file.merge(&changed);
+        self.write_file(file)
+    }
+
+    // Saves speed, quirks, colours and keymap as they are now for the loaded
+    // ROM. The config file gets back what it had for them before this run,
+    // they were only changed for this ROM.
+    pub fn save_profile(&mut self) -> Result<(), ConfigError> {
+        let Some(path) = &self.profile_path else {
+            return Ok(());
+        };
+
+        let mut profile = self.profile.clone().unwrap_or_default();
+        profile.merge(&Config::capture_profile(self));
+        write_config(path, &profile)?;
+
+        let mut file = self.file.clone();
+        file.unset(&profile);
+        file.merge(&self.loaded.only(&profile));
+        self.pinned.merge(&profile);
+        self.profile = Some(profile);
+        self.write_file(file)
+    }
+
+    fn write_file(&mut self, file: Config) -> Result<(), ConfigError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if file == self.file {
+            return Ok(());
+        }
+
+        write_config(path, &file)?;
+        self.file = file;
+        Ok(())
+    }
+
+    // colours indexed by the XO-CHIP plane combination of a pixel
+    pub fn palette(&self) -> [[f32; 4]; 4] {
+        [
+            self.bg_color,
+            self.fg_color,
+            self.plane2_color,
+            self.overlap_color,
+        ]
+    }
+}
+
+// The settings as flags. They win over everything else for the run they're
+// given for, and aren't saved.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct SettingsArgs {
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Settings file to load and save [default: settings.toml in the config directory]"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, value_enum, help = "Quirks preset")]
+    pub quirks: Option<QuirksPreset>,
+
+    #[arg(
+        long,
+        value_name = "TICKS",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Instructions per 60Hz frame"
+    )]
+    pub speed: Option<u32>,
+
+    #[arg(long, value_name = "COLOR", value_parser = config::parse_color, help = "Foreground colour, #rrggbb")]
+    pub fg: Option<[f32; 4]>,
+
+    #[arg(long, value_name = "COLOR", value_parser = config::parse_color, help = "Background colour, #rrggbb")]
+    pub bg: Option<[f32; 4]>,
+
+    #[arg(long, value_name = "COLOR", value_parser = config::parse_color, help = "XO-CHIP plane 2 colour, #rrggbb")]
+    pub plane2: Option<[f32; 4]>,
+
+    #[arg(long, value_name = "COLOR", value_parser = config::parse_color, help = "XO-CHIP colour where both planes are set, #rrggbb")]
+    pub overlap: Option<[f32; 4]>,
+
+    #[arg(
+        long,
+        value_name = "HZ",
+        value_parser = parse_beep_freq,
+        help = "Beep frequency, without the major scale, 55 to 880"
+    )]
+    pub beep_freq: Option<f32>,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Beep in a major scale instead of at one frequency"
+    )]
+    pub scale_mode: Option<bool>,
+
+    #[arg(long, help = "Turn post-processing off")]
+    pub no_postprocess: bool,
+
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        value_parser = parse_sepia,
+        help = "Sepia amount of the post-processing, 0 to 1"
+    )]
+    pub sepia: Option<f32>,
+
+    #[arg(long, value_name = "SECONDS", help = "How far back rewinding goes")]
+    pub rewind_seconds: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Open the window without decorations"
+    )]
+    pub borderless: Option<bool>,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Give the window a shadow, macOS only"
+    )]
+    pub window_shadow: Option<bool>,
+}
+
+impl SettingsArgs {
+    // for `Settings::load`
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(config::default_path)
+    }
+
+    // the settings that were given, to `pin`
+    pub fn config(&self) -> Config {
+        Config {
+            fg_color: self.fg,
+            bg_color: self.bg,
+            plane2_color: self.plane2,
+            overlap_color: self.overlap,
+            window_has_shadow: self.window_shadow,
+            borderless: self.borderless,
+            ticks_per_frame: self.speed,
+            rewind_seconds: self.rewind_seconds,
+            beep_freq: self.beep_freq,
+            scale_mode: self.scale_mode,
+            pp_enabled: self.no_postprocess.then_some(false),
+            sepia_amount: self.sepia,
+            quirks: self.quirks.map(QuirksPreset::quirks),
+            ..Default::default()
         }
     }
 }
+
+// the settings window's ranges
+fn parse_beep_freq(s: &str) -> Result<f32, String> {
+    parse_float_in(s, BEEP_FREQ_RANGE)
+}
+
+fn parse_sepia(s: &str) -> Result<f32, String> {
+    parse_float_in(s, SEPIA_RANGE)
+}
+
+fn parse_float_in(s: &str, range: RangeInclusive<f32>) -> Result<f32, String> {
+    let val: f32 = s.parse().map_err(|_| format!("`{}` isn't a number", s))?;
+    float_in(val, range)
+}
+
+// NaN isn't in any range
+pub(crate) fn float_in(val: f32, range: RangeInclusive<f32>) -> Result<f32, String> {
+    if !range.contains(&val) {
+        return Err(format!(
+            "{} isn't between {} and {}",
+            val,
+            range.start(),
+            range.end()
+        ));
+    }
+
+    Ok(val)
+}
+
+// None if there is no file yet, warnings about single settings are printed
+fn read_config(path: &Path) -> Result<Option<Config>, ConfigError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let (config, warnings) = Config::parse(&text)?;
+    for warning in warnings {
+        eprintln!("{}: {}", path.display(), warning);
+    }
+
+    Ok(Some(config))
+}
+
+fn write_config(path: &Path, config: &Config) -> Result<(), ConfigError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, config.to_toml()?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory under the system temp dir, removed again when dropped so
+    // a failing test doesn't leave it behind.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("chip8-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_save_pinned() {
+        let dir = TempDir::new("settings");
+        let path = dir.0.join("settings.toml");
+
+        let mut settings = Settings::load(Some(path.clone()));
+        settings.pin(&Config {
+            quirks: Some(crate::vm::QuirksPreset::Schip.quirks()),
+            ..Default::default()
+        });
+        settings.ticks_per_frame = 300;
+        settings.save().unwrap();
+
+        let loaded = Settings::load(Some(path.clone()));
+        assert_eq!(loaded.ticks_per_frame, 300);
+        assert_eq!(loaded.quirks, Quirks::default());
+        assert_eq!(loaded.sepia_amount, settings.sepia_amount);
+    }
+
+    #[test]
+    fn test_profile() {
+        let dir = TempDir::new("profile");
+        let path = dir.0.join("settings.toml");
+
+        let mut settings = Settings::load(Some(path.clone()));
+        settings.load_profile("abc");
+        assert!(settings.can_save_profile() && !settings.has_profile());
+
+        // changed in the window, so saved for every ROM first
+        settings.ticks_per_frame = 500;
+        settings.sepia_amount = 0.25;
+        settings.save().unwrap();
+        settings.save_profile().unwrap();
+        assert!(dir.0.join("roms/abc.toml").exists());
+
+        // the config file gets its speed back, the rest stays
+        let other_rom = Settings::load(Some(path.clone()));
+        assert_eq!(other_rom.ticks_per_frame, Settings::new().ticks_per_frame);
+        assert_eq!(other_rom.sepia_amount, 0.25);
+
+        let mut same_rom = Settings::load(Some(path.clone()));
+        same_rom.load_profile("abc");
+        assert!(same_rom.has_profile());
+        assert_eq!(same_rom.ticks_per_frame, 500);
+
+        // and changing the speed now doesn't go to the config file
+        same_rom.ticks_per_frame = 20;
+        same_rom.save().unwrap();
+        assert_eq!(
+            Settings::load(Some(path.clone())).ticks_per_frame,
+            Settings::new().ticks_per_frame
+        );
+    }
+
+    #[test]
+    fn test_args() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            settings: SettingsArgs,
+        }
+
+        let dir = TempDir::new("args");
+        let path = dir.0.join("settings.toml");
+        std::fs::create_dir_all(dir.0.join("roms")).unwrap();
+        std::fs::write(&path, "ticks_per_frame = 20\nsepia_amount = 0.1\n").unwrap();
+        std::fs::write(dir.0.join("roms/abc.toml"), "ticks_per_frame = 30\n").unwrap();
+
+        let cli = <Cli as clap::Parser>::try_parse_from([
+            "chip8",
+            "--speed",
+            "40",
+            "--fg",
+            "#00ff00",
+            "--no-postprocess",
+            "--scale-mode=false",
+        ])
+        .unwrap();
+        for args in [
+            ["chip8", "--sepia", "1.5"],
+            ["chip8", "--sepia", "NaN"],
+            ["chip8", "--beep-freq", "20"],
+            ["chip8", "--beep-freq", "nan"],
+        ] {
+            assert!(
+                <Cli as clap::Parser>::try_parse_from(args).is_err(),
+                "{:?}",
+                args
+            );
+        }
+        let bounds = ["chip8", "--sepia", "1", "--beep-freq", "880"];
+        assert!(<Cli as clap::Parser>::try_parse_from(bounds).is_ok());
+
+        // command line over the ROM's profile over the config file
+        let mut settings = Settings::load(Some(path.clone()));
+        settings.load_profile("abc");
+        assert_eq!(settings.ticks_per_frame, 30);
+        settings.pin(&cli.settings.config());
+        assert_eq!(settings.ticks_per_frame, 40);
+        assert_eq!(settings.fg_color, [0.0, 1.0, 0.0, 1.0]);
+        assert!(!settings.pp_enabled && !settings.scale_mode);
+        assert_eq!(settings.sepia_amount, 0.1);
+
+        // and none of it is saved
+        settings.sepia_amount = 0.2;
+        settings.save().unwrap();
+        let loaded = Settings::load(Some(path));
+        assert_eq!(loaded.ticks_per_frame, 20);
+        assert!(loaded.pp_enabled && loaded.scale_mode);
+        assert_eq!(loaded.sepia_amount, 0.2);
+    }
+}
@@ -0,0 +1,512 @@
+#[cfg(feature = "gui")]
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Serialize, Serializer};
+#[cfg(feature = "gui")]
+use winit::keyboard::KeyCode;
+
+#[cfg(feature = "gui")]
+use crate::app::keymap::{self, KEYMAP};
+use crate::settings::{self, BEEP_FREQ_RANGE, SEPIA_RANGE, Settings};
+use crate::vm::Quirks;
+
+// Bumped when a setting changes meaning, older files get migrated in `parse`.
+// Settings that are only added don't need it, missing ones keep their default.
+pub const VERSION: u32 = 1;
+
+// `~/.config/chip8/settings.toml` on Linux, wherever `dirs` puts config elsewhere
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("chip8").join("settings.toml"))
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Write(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{}", err),
+            ConfigError::Parse(err) => write!(f, "{}", err),
+            ConfigError::Write(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+// Some of the settings, the ones that are None are left as they are. The
+// config file is one of these, so is anything else that overrides it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Config {
+    #[serde(skip)]
+    pub version: u32,
+
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_color"
+    )]
+    pub fg_color: Option<[f32; 4]>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_color"
+    )]
+    pub bg_color: Option<[f32; 4]>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_color"
+    )]
+    pub plane2_color: Option<[f32; 4]>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_color"
+    )]
+    pub overlap_color: Option<[f32; 4]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_has_shadow: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub borderless: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticks_per_frame: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewind_seconds: Option<u32>,
+
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_float"
+    )]
+    pub beep_freq: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_mode: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pp_enabled: Option<bool>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_float"
+    )]
+    pub sepia_amount: Option<f32>,
+
+    // tables, so they have to come after the plain values
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<Quirks>,
+    // `[keymap]` maps CHIP-8 keys 0-F to winit key names, missing ones keep
+    // the default. Without a window it's one of the unknown keys below.
+    #[cfg(feature = "gui")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_keymap"
+    )]
+    pub keymap: Option<[KeyCode; 16]>,
+
+    // keys this version doesn't know, kept so saving doesn't lose what a
+    // newer version wrote
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+impl Config {
+    // Never fails on a setting, one with a bad value is skipped and comes back
+    // as a warning. Only a file that isn't TOML at all is an error.
+    pub fn parse(text: &str) -> Result<(Self, Vec<String>), ConfigError> {
+        let table: toml::Table = text.parse().map_err(ConfigError::Parse)?;
+        let mut config = Config {
+            version: VERSION,
+            ..Default::default()
+        };
+        let mut warnings = Vec::new();
+
+        for (key, val) in table {
+            let result = match key.as_str() {
+                "version" => value(val).map(|version| config.version = version),
+                "fg_color" => color_value(val).map(|color| config.fg_color = Some(color)),
+                "bg_color" => color_value(val).map(|color| config.bg_color = Some(color)),
+                "plane2_color" => color_value(val).map(|color| config.plane2_color = Some(color)),
+                "overlap_color" => color_value(val).map(|color| config.overlap_color = Some(color)),
+                "window_has_shadow" => value(val).map(|val| config.window_has_shadow = Some(val)),
+                "borderless" => value(val).map(|val| config.borderless = Some(val)),
+                "ticks_per_frame" => value(val)
+                    .and_then(nonzero)
+                    .map(|val| config.ticks_per_frame = Some(val)),
+                "rewind_seconds" => value(val).map(|val| config.rewind_seconds = Some(val)),
+                "beep_freq" => value(val)
+                    .and_then(|val| settings::float_in(val, BEEP_FREQ_RANGE))
+                    .map(|val| config.beep_freq = Some(val)),
+                "scale_mode" => value(val).map(|val| config.scale_mode = Some(val)),
+                "pp_enabled" => value(val).map(|val| config.pp_enabled = Some(val)),
+                "sepia_amount" => value(val)
+                    .and_then(|val| settings::float_in(val, SEPIA_RANGE))
+                    .map(|val| config.sepia_amount = Some(val)),
+                "quirks" => value(val).map(|val| config.quirks = Some(val)),
+                #[cfg(feature = "gui")]
+                "keymap" => keymap_value(val).map(|val| config.keymap = Some(val)),
+                _ => {
+                    config.unknown.insert(key, val);
+                    continue;
+                }
+            };
+
+            if let Err(err) = result {
+                warnings.push(format!("`{}` skipped, {}", key, err));
+            }
+        }
+
+        if config.version > VERSION {
+            warnings.push(format!(
+                "written by a newer version ({}), settings it added are left alone",
+                config.version
+            ));
+        }
+
+        Ok((config, warnings))
+    }
+
+    // every setting as it is now
+    pub fn capture(settings: &Settings) -> Self {
+        Self {
+            version: VERSION,
+            fg_color: Some(settings.fg_color),
+            bg_color: Some(settings.bg_color),
+            plane2_color: Some(settings.plane2_color),
+            overlap_color: Some(settings.overlap_color),
+            window_has_shadow: Some(settings.window_has_shadow),
+            borderless: Some(settings.borderless),
+            ticks_per_frame: Some(settings.ticks_per_frame),
+            rewind_seconds: Some(settings.rewind_seconds),
+            beep_freq: Some(settings.beep_freq),
+            scale_mode: Some(settings.scale_mode),
+            pp_enabled: Some(settings.pp_enabled),
+            sepia_amount: Some(settings.sepia_amount),
+            quirks: Some(settings.quirks),
+            #[cfg(feature = "gui")]
+            keymap: Some(settings.keymap),
+            unknown: toml::Table::new(),
+        }
+    }
+
+    // what a ROM profile keeps, the rest is the same for every ROM
+    pub fn capture_profile(settings: &Settings) -> Self {
+        Self {
+            fg_color: Some(settings.fg_color),
+            bg_color: Some(settings.bg_color),
+            plane2_color: Some(settings.plane2_color),
+            overlap_color: Some(settings.overlap_color),
+            ticks_per_frame: Some(settings.ticks_per_frame),
+            quirks: Some(settings.quirks),
+            #[cfg(feature = "gui")]
+            keymap: Some(settings.keymap),
+            ..Default::default()
+        }
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.fg_color = self.fg_color.unwrap_or(settings.fg_color);
+        settings.bg_color = self.bg_color.unwrap_or(settings.bg_color);
+        settings.plane2_color = self.plane2_color.unwrap_or(settings.plane2_color);
+        settings.overlap_color = self.overlap_color.unwrap_or(settings.overlap_color);
+        settings.window_has_shadow = self.window_has_shadow.unwrap_or(settings.window_has_shadow);
+        settings.borderless = self.borderless.unwrap_or(settings.borderless);
+        settings.ticks_per_frame = self.ticks_per_frame.unwrap_or(settings.ticks_per_frame);
+        settings.rewind_seconds = self.rewind_seconds.unwrap_or(settings.rewind_seconds);
+        settings.beep_freq = self.beep_freq.unwrap_or(settings.beep_freq);
+        settings.scale_mode = self.scale_mode.unwrap_or(settings.scale_mode);
+        settings.pp_enabled = self.pp_enabled.unwrap_or(settings.pp_enabled);
+        settings.sepia_amount = self.sepia_amount.unwrap_or(settings.sepia_amount);
+        settings.quirks = self.quirks.unwrap_or(settings.quirks);
+        #[cfg(feature = "gui")]
+        {
+            settings.keymap = self.keymap.unwrap_or(settings.keymap);
+        }
+    }
+
+    // the settings `other` has replace ours
+    pub fn merge(&mut self, other: &Config) {
+        self.fg_color = other.fg_color.or(self.fg_color);
+        self.bg_color = other.bg_color.or(self.bg_color);
+        self.plane2_color = other.plane2_color.or(self.plane2_color);
+        self.overlap_color = other.overlap_color.or(self.overlap_color);
+        self.window_has_shadow = other.window_has_shadow.or(self.window_has_shadow);
+        self.borderless = other.borderless.or(self.borderless);
+        self.ticks_per_frame = other.ticks_per_frame.or(self.ticks_per_frame);
+        self.rewind_seconds = other.rewind_seconds.or(self.rewind_seconds);
+        self.beep_freq = other.beep_freq.or(self.beep_freq);
+        self.scale_mode = other.scale_mode.or(self.scale_mode);
+        self.pp_enabled = other.pp_enabled.or(self.pp_enabled);
+        self.sepia_amount = other.sepia_amount.or(self.sepia_amount);
+        self.quirks = other.quirks.or(self.quirks);
+        #[cfg(feature = "gui")]
+        {
+            self.keymap = other.keymap.or(self.keymap);
+        }
+    }
+
+    // the settings `other` has are dropped from ours
+    pub fn unset(&mut self, other: &Config) {
+        self.fg_color = self.fg_color.filter(|_| other.fg_color.is_none());
+        self.bg_color = self.bg_color.filter(|_| other.bg_color.is_none());
+        self.plane2_color = self.plane2_color.filter(|_| other.plane2_color.is_none());
+        self.overlap_color = self.overlap_color.filter(|_| other.overlap_color.is_none());
+        self.window_has_shadow = self
+            .window_has_shadow
+            .filter(|_| other.window_has_shadow.is_none());
+        self.borderless = self.borderless.filter(|_| other.borderless.is_none());
+        self.ticks_per_frame = self
+            .ticks_per_frame
+            .filter(|_| other.ticks_per_frame.is_none());
+        self.rewind_seconds = self
+            .rewind_seconds
+            .filter(|_| other.rewind_seconds.is_none());
+        self.beep_freq = self.beep_freq.filter(|_| other.beep_freq.is_none());
+        self.scale_mode = self.scale_mode.filter(|_| other.scale_mode.is_none());
+        self.pp_enabled = self.pp_enabled.filter(|_| other.pp_enabled.is_none());
+        self.sepia_amount = self.sepia_amount.filter(|_| other.sepia_amount.is_none());
+        self.quirks = self.quirks.filter(|_| other.quirks.is_none());
+        #[cfg(feature = "gui")]
+        {
+            self.keymap = self.keymap.filter(|_| other.keymap.is_none());
+        }
+    }
+
+    // the settings `other` has too, with our values
+    pub fn only(&self, other: &Config) -> Config {
+        let mut rest = self.clone();
+        rest.unset(other);
+        let mut only = self.clone();
+        only.unset(&rest);
+        only
+    }
+
+    // the file as `save` writes it, every setting that is Some
+    pub fn to_toml(&self) -> Result<String, ConfigError> {
+        let body = toml::to_string(self).map_err(ConfigError::Write)?;
+        Ok(format!(
+            "version = {}\n\n{}",
+            self.version.max(VERSION),
+            body
+        ))
+    }
+}
+
+fn value<T: serde::de::DeserializeOwned>(val: toml::Value) -> Result<T, String> {
+    val.try_into()
+        .map_err(|err: toml::de::Error| err.message().to_string())
+}
+
+// no instructions a frame would never get past the first one
+fn nonzero(val: u32) -> Result<u32, String> {
+    if val == 0 {
+        return Err("it has to be at least 1".to_string());
+    }
+
+    Ok(val)
+}
+
+fn color_value(val: toml::Value) -> Result<[f32; 4], String> {
+    parse_color(&value::<String>(val)?)
+}
+
+#[cfg(feature = "gui")]
+fn keymap_value(val: toml::Value) -> Result<[KeyCode; 16], String> {
+    let mut keymap = KEYMAP;
+    for (key, name) in value::<BTreeMap<String, String>>(val)? {
+        let idx = u8::from_str_radix(&key, 16)
+            .ok()
+            .filter(|&idx| idx < 16)
+            .ok_or_else(|| format!("`{}` isn't a CHIP-8 key, 0-F", key))?;
+        keymap[idx as usize] =
+            keymap::parse_key(&name).ok_or_else(|| format!("unknown key `{}`", name))?;
+    }
+
+    Ok(keymap)
+}
+
+// `#rrggbb` or `#rrggbbaa`
+pub fn parse_color(s: &str) -> Result<[f32; 4], String> {
+    let digits = s.strip_prefix('#').unwrap_or(s);
+    if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+        return Err(format!("`{}` isn't a #rrggbb colour", s));
+    }
+
+    let mut color = [1.0; 4];
+    for (idx, channel) in color.iter_mut().enumerate().take(digits.len() / 2) {
+        let byte = u8::from_str_radix(&digits[idx * 2..idx * 2 + 2], 16)
+            .map_err(|_| format!("`{}` isn't a #rrggbb colour", s))?;
+        *channel = byte as f32 / 255.0;
+    }
+
+    Ok(color)
+}
+
+pub fn format_color(color: [f32; 4]) -> String {
+    let [r, g, b, a] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    match a {
+        255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        _ => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+    }
+}
+
+fn serialize_color<S: Serializer>(
+    color: &Option<[f32; 4]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    color.map(format_color).serialize(serializer)
+}
+
+#[cfg(feature = "gui")]
+fn serialize_keymap<S: Serializer>(
+    keymap: &Option<[KeyCode; 16]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    keymap
+        .map(|keymap| {
+            (0..16)
+                .map(|idx| {
+                    let name = keymap::key_name(keymap[idx]).unwrap_or("Unidentified");
+                    (format!("{:X}", idx), name)
+                })
+                .collect::<BTreeMap<_, _>>()
+        })
+        .serialize(serializer)
+}
+
+// an f32 widened as is comes out as 0.3700000047683716
+fn serialize_float<S: Serializer>(val: &Option<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+    val.map(|val| (val as f64 * 1000.0).round() / 1000.0)
+        .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let config = Config {
+            version: VERSION,
+            fg_color: Some([1.0, 0.0, 0.2, 1.0]),
+            bg_color: Some([0.0, 0.0, 0.0, 0.6]),
+            ticks_per_frame: Some(200),
+            sepia_amount: Some(0.37),
+            quirks: Some(Quirks {
+                vf_reset: true,
+                ..Quirks::default()
+            }),
+            ..Default::default()
+        };
+
+        let text = config.to_toml().unwrap();
+        assert!(text.contains("fg_color = \"#ff0033\"\n"), "{}", text);
+        assert!(text.contains("bg_color = \"#00000099\"\n"), "{}", text);
+        assert!(text.contains("sepia_amount = 0.37\n"), "{}", text);
+        assert!(!text.contains("beep_freq"), "{}", text);
+
+        let (parsed, warnings) = Config::parse(&text).unwrap();
+        assert_eq!(warnings, Vec::<String>::new());
+        assert_eq!(parsed.ticks_per_frame, Some(200));
+        assert_eq!(parsed.quirks, config.quirks);
+        assert_eq!(parsed.to_toml().unwrap(), text);
+    }
+
+    #[test]
+    fn test_tolerant() {
+        let text = "version = 7\n\
+                    ticks_per_frame = \"fast\"\n\
+                    fg_color = \"#12345\"\n\
+                    beep_freq = 440\n\
+                    crt_curvature = 0.2\n\
+                    [quirks]\n\
+                    clip_sprites = true\n\
+                    wrap_memory = true\n";
+        let (config, warnings) = Config::parse(text).unwrap();
+
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(warnings[0].contains("fg_color"));
+        assert!(warnings[1].contains("ticks_per_frame"));
+        assert!(warnings[2].contains("newer version"));
+
+        assert_eq!(config.ticks_per_frame, None);
+        assert_eq!(config.fg_color, None);
+        assert_eq!(config.beep_freq, Some(440.0));
+        assert!(config.quirks.unwrap().clip_sprites);
+        assert!(!config.quirks.unwrap().vf_reset);
+
+        // what a newer version wrote survives a save
+        let text = config.to_toml().unwrap();
+        assert!(text.contains("version = 7\n"), "{}", text);
+        assert!(text.contains("crt_curvature = 0.2\n"), "{}", text);
+
+        assert!(Config::parse("ticks_per_frame = ").is_err());
+    }
+
+    #[test]
+    fn test_ranges() {
+        let text = "ticks_per_frame = 0
+                    beep_freq = 20000.0
+                    sepia_amount = -1.0
+";
+        let (config, warnings) = Config::parse(text).unwrap();
+
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(warnings[0].contains("beep_freq"), "{:?}", warnings);
+        assert!(warnings[1].contains("sepia_amount"), "{:?}", warnings);
+        assert!(warnings[2].contains("ticks_per_frame"), "{:?}", warnings);
+        assert_eq!(
+            config,
+            Config {
+                version: VERSION,
+                ..Default::default()
+            }
+        );
+
+        let text = "ticks_per_frame = 1
+beep_freq = 880.0
+sepia_amount = 0.0
+";
+        let (config, warnings) = Config::parse(text).unwrap();
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        assert_eq!(config.ticks_per_frame, Some(1));
+        assert_eq!(config.beep_freq, Some(880.0));
+        assert_eq!(config.sepia_amount, Some(0.0));
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn test_keymap() {
+        let (config, warnings) = Config::parse("[keymap]\n5 = \"arrowup\"\n").unwrap();
+        assert!(warnings.is_empty());
+        let keymap = config.keymap.unwrap();
+        assert_eq!(keymap[5], KeyCode::ArrowUp);
+        assert_eq!(keymap[0], KEYMAP[0]);
+
+        let text = config.to_toml().unwrap();
+        assert!(text.contains("[keymap]\n0 = \"KeyX\"\n"), "{}", text);
+        assert!(text.contains("5 = \"ArrowUp\"\n"), "{}", text);
+        assert_eq!(Config::parse(&text).unwrap().0.keymap, config.keymap);
+
+        let (config, warnings) = Config::parse("[keymap]\nG = \"KeyA\"\n").unwrap();
+        assert_eq!(config.keymap, None);
+        assert!(warnings[0].contains("`G`"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(parse_color("#ff0000").unwrap(), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(parse_color("00ff0000").unwrap(), [0.0, 1.0, 0.0, 0.0]);
+        assert!(parse_color("#ff00").is_err());
+        assert!(parse_color("#gg0000").is_err());
+        assert_eq!(format_color([0.0, 0.5, 1.0, 1.0]), "#0080ff");
+    }
+}
@@ -3,15 +3,17 @@ struct ScreenSizeUniform {
     height: f32,
 };
 
+// two 128x64 bitplanes, plane 2 starts at PLANE_WORDS
 struct VideoBuffer {
-    pixels: array<u32, 64>,
+    width: u32,
+    height: u32,
+    pixels: array<u32, 512>,
 };
 
+// background, plane 1, plane 2, both planes
 @group(0) @binding(0)
-var<uniform> u_fg_color: vec4<f32>;
+var<uniform> u_palette: array<vec4<f32>, 4>;
 @group(0) @binding(1)
-var<uniform> u_bg_color: vec4<f32>;
-@group(0) @binding(2)
 var<uniform> u_size: ScreenSizeUniform;
 
 @group(1) @binding(0)
@@ -36,8 +38,9 @@ fn vs_main(
 
 @fragment
 fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
-    let CHIP8_WIDTH: f32 = 64.0;
-    let CHIP8_HEIGHT: f32 = 32.0;
+    // 64x32 in lores, 128x64 in SCHIP hires
+    let CHIP8_WIDTH: f32 = f32(video.width);
+    let CHIP8_HEIGHT: f32 = f32(video.height);
 
     let px = pos.x;
     let py = pos.y;
@@ -48,14 +51,13 @@ fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
     let chip_x = u32(clamp(floor(px * CHIP8_WIDTH / w), 0.0, CHIP8_WIDTH - 1.0));
     let chip_y = u32(clamp(floor(py * CHIP8_HEIGHT / h), 0.0, CHIP8_HEIGHT - 1.0));
 
+    let PLANE_WORDS: u32 = 256u;
+
     let idx = chip_y * u32(CHIP8_WIDTH) + chip_x;
     let word = idx / 32u;
     let bit = idx % 32u;
-    let pix = (video.pixels[word] >> bit) & 1u;
+    let plane1 = (video.pixels[word] >> bit) & 1u;
+    let plane2 = (video.pixels[PLANE_WORDS + word] >> bit) & 1u;
 
-    if pix == 1u {
-        return u_fg_color;
-    } else {
-        return u_bg_color;
-    }
+    return u_palette[(plane2 << 1u) | plane1];
 }
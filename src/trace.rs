@@ -0,0 +1,395 @@
+pub mod diff;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use crate::debugger;
+use crate::vm::{Vm, instruction};
+
+// Trace layout, all integers little endian:
+//   magic "C8TR", version u16,
+//   then per traced instruction:
+//     cycles since the previous record, LEB128 (the first counts from 0)
+//     pc u16, opcode u16, ireg u16 and stack depth u8 after the instruction,
+//     mask u16 of the registers it changed, their new values u8, V0 first
+pub const MAGIC: &[u8; 4] = b"C8TR";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::BadMagic => write!(f, "not a trace"),
+            TraceError::UnsupportedVersion(version) => {
+                write!(f, "unsupported trace version {}", version)
+            }
+            TraceError::Truncated => write!(f, "trace is truncated"),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub ireg: u16,
+    pub sp: u8,
+    // (register, new value) for every register the instruction changed
+    pub changes: Vec<(u8, u8)>,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10}  {:03x}  {:04x}  {:<20}  I={:03x} SP={}",
+            self.cycle,
+            self.pc,
+            self.opcode,
+            // I is F000 nnnn's address once it has run
+            instruction::decode(self.opcode).display_with(self.ireg),
+            self.ireg,
+            self.sp
+        )?;
+
+        for (reg, val) in &self.changes {
+            write!(f, " V{:X}={:02x}", reg, val)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub addrs: Option<RangeInclusive<u16>>,
+    pub cycles: Option<RangeInclusive<u64>>,
+}
+
+impl Filter {
+    pub fn matches(&self, cycle: u64, pc: u16) -> bool {
+        self.addrs.as_ref().is_none_or(|addrs| addrs.contains(&pc))
+            && self
+                .cycles
+                .as_ref()
+                .is_none_or(|cycles| cycles.contains(&cycle))
+    }
+}
+
+// `--trace` and its filters, shared by chip8 and chip8-headless
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct TraceArgs {
+    #[arg(
+        long = "trace",
+        value_name = "FILE",
+        help = "Write a binary execution trace, see `chip8 trace-dump`"
+    )]
+    pub trace_path: Option<PathBuf>,
+
+    #[arg(
+        long = "trace-addr",
+        value_name = "RANGE",
+        requires = "trace_path",
+        value_parser = parse_addr_range,
+        help = "Only trace instructions at START[-END]"
+    )]
+    pub trace_addrs: Option<RangeInclusive<u16>>,
+
+    #[arg(
+        long = "trace-cycles",
+        value_name = "RANGE",
+        requires = "trace_path",
+        value_parser = parse_cycle_range,
+        help = "Only trace cycles START[-END], `START-` runs to the end"
+    )]
+    pub trace_cycles: Option<RangeInclusive<u64>>,
+}
+
+impl TraceArgs {
+    pub fn tracer(&self) -> io::Result<Option<Tracer>> {
+        let Some(path) = &self.trace_path else {
+            return Ok(None);
+        };
+
+        let filter = Filter {
+            addrs: self.trace_addrs.clone(),
+            cycles: self.trace_cycles.clone(),
+        };
+        Tracer::create(path, filter).map(Some)
+    }
+}
+
+// Writes a record for every tick that passes the filter. Call `before_tick`
+// and `after_tick` around `Vm::tick`, a tick that fails isn't recorded.
+pub struct Tracer<W: Write = BufWriter<File>> {
+    out: W,
+    filter: Filter,
+    cycle: u64,
+    // cycle of the last record, records only store the distance to it
+    last: u64,
+    // pc, opcode and registers before the tick being traced
+    pending: Option<(u16, u16, [u8; 16])>,
+}
+
+impl Tracer {
+    pub fn create(path: &Path, filter: Filter) -> io::Result<Self> {
+        Tracer::new(BufWriter::new(File::create(path)?), filter)
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(mut out: W, filter: Filter) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(Self {
+            out,
+            filter,
+            cycle: 0,
+            last: 0,
+            pending: None,
+        })
+    }
+
+    pub fn before_tick(&mut self, vm: &Vm) {
+        let pc = vm.pc();
+        self.pending = self.filter.matches(self.cycle, pc).then(|| {
+            let byte = |addr: u16| vm.mem().get(addr as usize).copied().unwrap_or(0);
+            let opcode = u16::from_be_bytes([byte(pc), byte(pc.wrapping_add(1))]);
+            (pc, opcode, *vm.reg())
+        });
+    }
+
+    pub fn after_tick(&mut self, vm: &Vm) -> io::Result<()> {
+        let cycle = self.cycle;
+        self.cycle += 1;
+
+        let Some((pc, opcode, before)) = self.pending.take() else {
+            return Ok(());
+        };
+
+        let mut buf = Vec::with_capacity(32);
+        write_varint(&mut buf, cycle - self.last);
+        self.last = cycle;
+
+        buf.extend(pc.to_le_bytes());
+        buf.extend(opcode.to_le_bytes());
+        buf.extend(vm.ireg().to_le_bytes());
+        buf.push(vm.sp() as u8);
+
+        let after = vm.reg();
+        let mask = (0..16)
+            .filter(|&idx| before[idx] != after[idx])
+            .fold(0u16, |mask, idx| mask | (1 << idx));
+        buf.extend(mask.to_le_bytes());
+        for idx in (0..16).filter(|idx| mask & (1 << idx) != 0) {
+            buf.push(after[idx]);
+        }
+
+        self.out.write_all(&buf)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        buf.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    buf.push(val as u8);
+}
+
+pub struct TraceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    cycle: u64,
+}
+
+impl<'a> TraceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, TraceError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+
+        let mut reader = Self {
+            data,
+            pos: MAGIC.len(),
+            cycle: 0,
+        };
+
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+
+        Ok(reader)
+    }
+
+    fn u8(&mut self) -> Result<u8, TraceError> {
+        let val = *self.data.get(self.pos).ok_or(TraceError::Truncated)?;
+        self.pos += 1;
+        Ok(val)
+    }
+
+    fn u16(&mut self) -> Result<u16, TraceError> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn varint(&mut self) -> Result<u64, TraceError> {
+        let mut val = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            val |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+
+        Err(TraceError::Truncated)
+    }
+
+    fn record(&mut self) -> Result<Record, TraceError> {
+        self.cycle = self.cycle.wrapping_add(self.varint()?);
+
+        let pc = self.u16()?;
+        let opcode = self.u16()?;
+        let ireg = self.u16()?;
+        let sp = self.u8()?;
+
+        let mask = self.u16()?;
+        let mut changes = Vec::new();
+        for reg in (0..16).filter(|reg| mask & (1 << reg) != 0) {
+            changes.push((reg, self.u8()?));
+        }
+
+        Ok(Record {
+            cycle: self.cycle,
+            pc,
+            opcode,
+            ireg,
+            sp,
+            changes,
+        })
+    }
+}
+
+impl Iterator for TraceReader<'_> {
+    type Item = Result<Record, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+
+        let record = self.record();
+        if record.is_err() {
+            // nothing sensible follows a broken record
+            self.pos = self.data.len();
+        }
+
+        Some(record)
+    }
+}
+
+// `0x200-0x2ff` or a single address
+pub fn parse_addr_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end) = (debugger::parse_addr(start)?, debugger::parse_addr(end)?);
+    if start > end {
+        return Err(format!("empty range: {}", s));
+    }
+
+    Ok(start..=end)
+}
+
+// `1000-2000`, `1000-` or a single cycle
+pub fn parse_cycle_range(s: &str) -> Result<RangeInclusive<u64>, String> {
+    let parse = |val: &str| {
+        val.trim()
+            .parse::<u64>()
+            .map_err(|_| format!("invalid cycle: {}", val))
+    };
+
+    let (start, end) = match s.split_once('-') {
+        Some((start, "")) => (parse(start)?, u64::MAX),
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(s)?, parse(s)?),
+    };
+    if start > end {
+        return Err(format!("empty range: {}", s));
+    }
+
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let rom = [
+            0x60, 0x05, // 200: LD V0, 0x05
+            0x6f, 0x01, // 202: LD VF, 0x01
+            0xa3, 0x00, // 204: LD I, 0x300
+            0x22, 0x0a, // 206: CALL 0x20a
+            0x12, 0x08, // 208: JP 0x208
+            0x70, 0x01, // 20a: ADD V0, 0x01
+            0x00, 0xee, // 20c: RET
+        ];
+
+        let mut vm = Vm::new();
+        vm.load_rom_bytes("test.ch8".to_string(), &rom).unwrap();
+
+        let filter = Filter {
+            addrs: Some(0x202..=0x20c),
+            cycles: Some(0..=4),
+        };
+        let mut tracer = Tracer::new(Vec::new(), filter).unwrap();
+        for _ in 0..8 {
+            tracer.before_tick(&vm);
+            vm.tick().unwrap();
+            tracer.after_tick(&vm).unwrap();
+        }
+        let data = tracer.finish().unwrap();
+
+        let records: Vec<Record> = TraceReader::new(&data)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let cycles: Vec<u64> = records.iter().map(|record| record.cycle).collect();
+        assert_eq!(cycles, [1, 2, 3, 4]);
+        assert_eq!(records[0].changes, [(0xf, 0x01)]);
+        assert_eq!(records[1].ireg, 0x300);
+        assert_eq!(records[2].sp, 1);
+        assert_eq!(
+            records[3].to_string(),
+            "         4  20a  7001  ADD V0, 0x01          I=300 SP=1 V0=06"
+        );
+
+        assert_eq!(
+            TraceReader::new(&data[..data.len() - 1])
+                .unwrap()
+                .last()
+                .unwrap(),
+            Err(TraceError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::headless::{RunError, Runner};
+use crate::vm::{Vm, instruction};
+
+// Machine state before an instruction runs, the way most emulators log it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub pc: u16,
+    pub opcode: u16,
+    pub reg: [u8; 16],
+    pub ireg: u16,
+    pub sp: u16,
+}
+
+impl Snapshot {
+    pub fn capture(vm: &Vm) -> Self {
+        let pc = vm.pc();
+        let byte = |addr: u16| vm.mem().get(addr as usize).copied().unwrap_or(0);
+
+        Self {
+            pc,
+            opcode: u16::from_be_bytes([byte(pc), byte(pc.wrapping_add(1))]),
+            reg: *vm.reg(),
+            ireg: vm.ireg(),
+            sp: vm.sp(),
+        }
+    }
+}
+
+// `PC=0200 OP=00E0 V0=00 ... VF=00 I=0000 SP=0`, which `parse_reference` reads back
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PC={:04X} OP={:04X}", self.pc, self.opcode)?;
+        for (idx, val) in self.reg.iter().enumerate() {
+            write!(f, " V{:X}={:02X}", idx, val)?;
+        }
+        write!(f, " I={:04X} SP={}", self.ireg, self.sp)
+    }
+}
+
+// One line of a reference log. Fields the other emulator doesn't log aren't compared.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefLine {
+    pub line: usize,
+    pub text: String,
+    pub pc: Option<u16>,
+    pub opcode: Option<u16>,
+    pub reg: [Option<u8>; 16],
+    pub ireg: Option<u16>,
+    pub sp: Option<u16>,
+}
+
+impl RefLine {
+    // what differs, as `V3: expected 0x05, got 0x04`
+    pub fn mismatches(&self, snapshot: &Snapshot) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut check = |name: String, expected: Option<u16>, got: u16| {
+            if let Some(expected) = expected.filter(|&expected| expected != got) {
+                out.push(format!(
+                    "{}: expected 0x{:02x}, got 0x{:02x}",
+                    name, expected, got
+                ));
+            }
+        };
+
+        check("PC".to_string(), self.pc, snapshot.pc);
+        check("opcode".to_string(), self.opcode, snapshot.opcode);
+        for idx in 0..16 {
+            check(
+                format!("V{:X}", idx),
+                self.reg[idx].map(u16::from),
+                snapshot.reg[idx] as u16,
+            );
+        }
+        check("I".to_string(), self.ireg, snapshot.ireg);
+        check("SP".to_string(), self.sp, snapshot.sp);
+
+        out
+    }
+}
+
+// Reads `KEY=VALUE` or `KEY:VALUE` fields separated by spaces or commas, a
+// space after the separator is fine too. Keys are PC, OP or OPCODE, V0-VF, I
+// and SP in any case, values are hex with an optional `0x` or `$`. Other
+// fields, blank lines and lines starting with `#` or `;` are skipped.
+pub fn parse_reference(text: &str) -> Result<Vec<RefLine>, String> {
+    let mut lines = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+
+        let mut entry = RefLine {
+            line: idx + 1,
+            text: trimmed.to_string(),
+            ..Default::default()
+        };
+        let mut found = false;
+
+        let mut fields = trimmed
+            .split(|ch: char| ch.is_whitespace() || ch == ',')
+            .filter(|field| !field.is_empty());
+        while let Some(field) = fields.next() {
+            let Some((key, val)) = field.split_once([':', '=']) else {
+                continue;
+            };
+            let val = match val {
+                "" => fields.next().unwrap_or(""),
+                val => val,
+            };
+
+            let key = key.to_ascii_uppercase();
+            let is_reg =
+                key.len() == 2 && key.starts_with('V') && key.as_bytes()[1].is_ascii_hexdigit();
+            if !matches!(key.as_str(), "PC" | "OP" | "OPCODE" | "I" | "SP") && !is_reg {
+                continue;
+            }
+
+            let digits = val
+                .strip_prefix("0x")
+                .or_else(|| val.strip_prefix("0X"))
+                .or_else(|| val.strip_prefix('$'))
+                .unwrap_or(val);
+            let val = u16::from_str_radix(digits, 16)
+                .map_err(|_| format!("line {}: invalid value for {}: `{}`", idx + 1, key, val))?;
+
+            match key.as_str() {
+                "PC" => entry.pc = Some(val),
+                "OP" | "OPCODE" => entry.opcode = Some(val),
+                "I" => entry.ireg = Some(val),
+                "SP" => entry.sp = Some(val),
+                _ => {
+                    let reg = u8::from_str_radix(&key[1..], 16).unwrap_or(0) as usize;
+                    let val = u8::try_from(val)
+                        .map_err(|_| format!("line {}: {} doesn't fit a register", idx + 1, key))?;
+                    entry.reg[reg] = Some(val);
+                }
+            }
+            found = true;
+        }
+
+        if !found {
+            return Err(format!(
+                "line {}: no PC, opcode, register, I or SP fields",
+                idx + 1
+            ));
+        }
+        lines.push(entry);
+    }
+
+    Ok(lines)
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    // every reference line matched
+    Match,
+    Diverged {
+        reference: RefLine,
+        snapshot: Snapshot,
+        mismatches: Vec<String>,
+    },
+    Halted(RunError),
+    // 00FD before the reference ran out
+    Exited,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    // instructions compared, including the divergent one
+    pub compared: usize,
+    // the last instructions before things went wrong, oldest first, and the
+    // index of the first of them
+    pub context: Vec<Snapshot>,
+    pub first: usize,
+    pub outcome: Outcome,
+}
+
+impl Report {
+    pub fn matched(&self) -> bool {
+        matches!(self.outcome, Outcome::Match)
+    }
+
+    fn write_context(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, snapshot) in self.context.iter().enumerate() {
+            writeln!(f, "  {:>8}  {}", self.first + idx, snapshot)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Match => {
+                return writeln!(f, "no divergence in {} instructions", self.compared);
+            }
+            Outcome::Diverged {
+                reference,
+                snapshot,
+                mismatches,
+            } => {
+                writeln!(
+                    f,
+                    "diverged at instruction {} (reference line {}):",
+                    self.compared - 1,
+                    reference.line
+                )?;
+                // the state differs, so it's usually the instruction before that is wrong
+                if let Some(prev) = self.context.last() {
+                    writeln!(
+                        f,
+                        "  after 0x{:03x}  {}",
+                        prev.pc,
+                        instruction::decode(prev.opcode).display_with(prev.ireg)
+                    )?;
+                }
+                for mismatch in mismatches {
+                    writeln!(f, "  {}", mismatch)?;
+                }
+                writeln!(f)?;
+                self.write_context(f)?;
+                writeln!(f, "> {:>8}  {}", self.compared - 1, snapshot)?;
+                writeln!(f, "  {:>8}  {}", "expected", reference.text)?;
+            }
+            Outcome::Halted(err) => {
+                writeln!(
+                    f,
+                    "the VM halted at instruction {}: {}",
+                    self.compared - 1,
+                    err
+                )?;
+                writeln!(f)?;
+                self.write_context(f)?;
+            }
+            Outcome::Exited => {
+                writeln!(
+                    f,
+                    "the program exited after {} instructions, the reference goes on",
+                    self.compared
+                )?;
+                writeln!(f)?;
+                self.write_context(f)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Steps the runner one instruction per reference line until they disagree.
+// `log` sees every snapshot taken, to write this side in the reference format.
+pub fn diff(
+    runner: &mut Runner,
+    reference: &[RefLine],
+    context: usize,
+    mut log: impl FnMut(&Snapshot),
+) -> Report {
+    let mut history = VecDeque::with_capacity(context + 1);
+    let mut compared = 0;
+
+    let outcome = 'run: {
+        for expected in reference {
+            if runner.vm.exited() {
+                break 'run Outcome::Exited;
+            }
+
+            let snapshot = Snapshot::capture(&runner.vm);
+            log(&snapshot);
+
+            compared += 1;
+            let mismatches = expected.mismatches(&snapshot);
+            if !mismatches.is_empty() {
+                break 'run Outcome::Diverged {
+                    reference: expected.clone(),
+                    snapshot,
+                    mismatches,
+                };
+            }
+
+            history.push_back(snapshot);
+            if history.len() > context {
+                history.pop_front();
+            }
+
+            if let Err(err) = runner.run_cycles(1) {
+                break 'run Outcome::Halted(err);
+            }
+        }
+
+        Outcome::Match
+    };
+
+    let context: Vec<Snapshot> = match outcome {
+        Outcome::Match => Vec::new(),
+        _ => history.into(),
+    };
+    // a divergent instruction isn't part of its own context
+    let diverged = matches!(outcome, Outcome::Diverged { .. }) as usize;
+
+    Report {
+        compared,
+        first: compared - context.len() - diverged,
+        context,
+        outcome,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner() -> Runner {
+        let rom = [
+            0x60, 0x05, // 200: LD V0, 0x05
+            0x70, 0x01, // 202: ADD V0, 0x01
+            0xa3, 0x00, // 204: LD I, 0x300
+            0x12, 0x06, // 206: JP 0x206
+        ];
+
+        let mut vm = Vm::new();
+        vm.load_rom_bytes("test.ch8".to_string(), &rom).unwrap();
+        Runner::new(vm, 10, Vec::new())
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        let lines = parse_reference(
+            "# comment\n\
+             PC:0200 OP:6005 V0:00 VF:00 I:0000\n\
+             pc=0x202, opcode=0x7001, v0=0x05, DT=3\n\
+             PC: $204 V0: 06 SP: 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].line, 2);
+        assert_eq!(lines[0].opcode, Some(0x6005));
+        assert_eq!(lines[1].pc, Some(0x202));
+        assert_eq!(lines[1].reg[0], Some(0x05));
+        assert_eq!(lines[1].reg[1], None);
+        assert_eq!(lines[2].pc, Some(0x204));
+        assert_eq!(lines[2].sp, Some(0));
+
+        assert!(parse_reference("PC=zz").is_err());
+        assert!(parse_reference("hello world").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        // our own log is a reference that matches
+        let mut log = Vec::new();
+        let reference: Vec<RefLine> = (0..4).map(|_| RefLine::default()).collect();
+        diff(&mut runner(), &reference, 2, |snapshot| {
+            log.push(snapshot.to_string())
+        });
+
+        let reference = parse_reference(&log.join("\n")).unwrap();
+        let report = diff(&mut runner(), &reference, 2, |_| {});
+        assert!(report.matched());
+        assert_eq!(report.compared, 4);
+
+        // an emulator that adds 2 instead of 1
+        let mut reference = reference;
+        reference[2].reg[0] = Some(0x07);
+        let report = diff(&mut runner(), &reference, 1, |_| {});
+        assert!(!report.matched());
+        assert_eq!(report.compared, 3);
+        assert_eq!(report.first, 1);
+        assert_eq!(report.context[0].pc, 0x202);
+
+        let Outcome::Diverged { mismatches, .. } = &report.outcome else {
+            panic!("expected a divergence, got {:?}", report.outcome);
+        };
+        assert_eq!(mismatches, &["V0: expected 0x07, got 0x06"]);
+    }
+}
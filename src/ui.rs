@@ -1,16 +1,27 @@
+pub mod debugger;
+
 use std::sync::{Arc, RwLock};
 
+use crate::app::platform;
+use crate::debugger::Debugger;
 use crate::egui::EguiRenderer;
-use crate::settings::Settings;
+use crate::settings::{BEEP_FREQ_RANGE, SEPIA_RANGE, Settings};
+use crate::vm::{Quirks, QuirksPreset, Vm, VmError};
+use debugger::DebuggerUi;
 
 pub struct Ui {
     settings: Arc<RwLock<Settings>>,
 
     fg_color: [f32; 4],
     bg_color: [f32; 4],
+    plane2_color: [f32; 4],
+    overlap_color: [f32; 4],
     window_has_shadow: bool,
+    borderless: bool,
 
     ticks_per_frame: u32,
+    quirks: Quirks,
+    rewind_seconds: u32,
     beep_freqency: f32,
     scale_mode: bool,
 
@@ -18,6 +29,10 @@ pub struct Ui {
     sepia_amount: f32,
 
     dirty: bool,
+    // changed in Settings but not written to the file yet
+    unsaved: bool,
+
+    debugger: DebuggerUi,
 }
 
 impl Ui {
@@ -25,10 +40,15 @@ impl Ui {
         let (
             fg_color,
             bg_color,
+            plane2_color,
+            overlap_color,
             ticks_per_frame,
+            quirks,
+            rewind_seconds,
             beep_freqency,
             scale_mode,
             window_has_shadow,
+            borderless,
             pp_enabled,
             sepia_amount,
         ) = {
@@ -37,10 +57,15 @@ impl Ui {
             (
                 settings.fg_color,
                 settings.bg_color,
+                settings.plane2_color,
+                settings.overlap_color,
                 settings.ticks_per_frame,
+                settings.quirks,
+                settings.rewind_seconds,
                 settings.beep_freq,
                 settings.scale_mode,
                 settings.window_has_shadow,
+                settings.borderless,
                 settings.pp_enabled,
                 settings.sepia_amount,
             )
@@ -50,18 +75,34 @@ impl Ui {
             settings,
             fg_color,
             bg_color,
+            plane2_color,
+            overlap_color,
             window_has_shadow,
+            borderless,
             ticks_per_frame,
+            quirks,
+            rewind_seconds,
             beep_freqency,
             scale_mode,
             pp_enabled,
             sepia_amount,
             dirty: false,
+            unsaved: false,
+            debugger: DebuggerUi::new(),
         }
     }
 
     pub fn draw(&mut self, egui_renderer: &EguiRenderer) {
         let ctx = egui_renderer.context();
+        let (rom_info, can_save_profile, has_profile) = {
+            let settings = self.settings.read().unwrap();
+            (
+                settings.rom_info(),
+                settings.can_save_profile(),
+                settings.has_profile(),
+            )
+        };
+        let mut save_profile = false;
 
         egui::Window::new("Settings")
             .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
@@ -70,6 +111,17 @@ impl Ui {
             .resizable(false)
             .frame(egui::Frame::window(&ctx.style()).inner_margin(egui::Margin::symmetric(15, 15)))
             .show(ctx, |ui| {
+                if let Some(info) = rom_info {
+                    ui.heading(&info.title);
+                    if !info.authors.is_empty() {
+                        ui.label(format!("by {}", info.authors.join(", ")));
+                    }
+                    if let Some(keys) = &info.keys {
+                        ui.label(keys);
+                    }
+                    ui.add_space(20.0);
+                }
+
                 ui.heading("Settings");
                 ui.add_space(10.0);
 
@@ -89,8 +141,30 @@ impl Ui {
                         .changed();
                 });
 
+                ui.horizontal(|ui| {
+                    ui.label("Plane 2");
+                    ui.add_space(50.0);
+                    self.dirty |= ui
+                        .color_edit_button_rgba_unmultiplied(&mut self.plane2_color)
+                        .changed();
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Overlap");
+                    ui.add_space(50.0);
+                    self.dirty |= ui
+                        .color_edit_button_rgba_unmultiplied(&mut self.overlap_color)
+                        .changed();
+                });
+
+                if platform::HAS_SHADOW {
+                    self.dirty |= ui
+                        .checkbox(&mut self.window_has_shadow, "Window has shadow")
+                        .changed();
+                }
+
                 self.dirty |= ui
-                    .checkbox(&mut self.window_has_shadow, "Window has shadow")
+                    .checkbox(&mut self.borderless, "Borderless window")
                     .changed();
 
                 ui.add_space(20.0);
@@ -101,13 +175,27 @@ impl Ui {
                         .changed();
                 });
 
+                ui.add_space(20.0);
+                ui.vertical(|ui| {
+                    ui.label("Rewind seconds");
+                    self.dirty |= ui
+                        .add(egui::Slider::new(&mut self.rewind_seconds, 0..=300).show_value(true))
+                        .changed();
+                });
+
+                ui.add_space(20.0);
+                ui.vertical(|ui| {
+                    ui.label("Quirks");
+                    self.draw_quirks(ui);
+                });
+
                 ui.add_space(20.0);
                 ui.vertical(|ui| {
                     ui.label("Beep frequency");
                     self.dirty |= ui
                         .add_enabled(
                             !self.scale_mode,
-                            egui::Slider::new(&mut self.beep_freqency, 55.0..=880.0)
+                            egui::Slider::new(&mut self.beep_freqency, BEEP_FREQ_RANGE)
                                 .show_value(true),
                         )
                         .changed();
@@ -126,14 +214,98 @@ impl Ui {
                 ui.vertical(|ui| {
                     ui.label("Sepia amount");
                     self.dirty |= ui
-                        .add(egui::Slider::new(&mut self.sepia_amount, 0.0..=1.0).show_value(true))
+                        .add(
+                            egui::Slider::new(&mut self.sepia_amount, SEPIA_RANGE).show_value(true),
+                        )
                         .changed();
                 });
+
+                if can_save_profile {
+                    ui.add_space(20.0);
+                    if has_profile {
+                        ui.label("Speed, quirks and colours are saved for this ROM");
+                    }
+                    save_profile = ui.button("Save as default for this ROM").clicked();
+                }
             });
 
         if self.dirty {
             self.update_settings();
         }
+
+        // not while a slider or colour is still being dragged
+        if !ctx.input(|input| input.pointer.any_down()) {
+            self.save_settings();
+        }
+
+        if save_profile {
+            self.save_profile();
+        }
+    }
+
+    fn draw_quirks(&mut self, ui: &mut egui::Ui) {
+        let selected = self
+            .quirks
+            .preset()
+            .map_or("Custom".to_string(), |preset| preset.to_string());
+
+        egui::ComboBox::from_id_salt("quirks_preset")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for preset in QuirksPreset::ALL {
+                    if ui
+                        .selectable_label(self.quirks.preset() == Some(preset), preset.to_string())
+                        .clicked()
+                    {
+                        self.quirks = preset.quirks();
+                        self.dirty = true;
+                    }
+                }
+            });
+
+        let quirks = &mut self.quirks;
+        self.dirty |= ui
+            .checkbox(&mut quirks.shift_uses_vy, "Shift uses Vy")
+            .changed();
+        self.dirty |= ui
+            .checkbox(&mut quirks.load_store_inc_i, "Load/store increments I")
+            .changed();
+        self.dirty |= ui
+            .checkbox(&mut quirks.jump_with_vx, "Jump with Vx")
+            .changed();
+        self.dirty |= ui.checkbox(&mut quirks.vf_reset, "VF reset").changed();
+        self.dirty |= ui
+            .checkbox(&mut quirks.clip_sprites, "Clip sprites")
+            .changed();
+    }
+
+    pub fn draw_halted(&mut self, egui_renderer: &EguiRenderer, err: &VmError) {
+        let ctx = egui_renderer.context();
+
+        egui::Window::new("Halted")
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::Vec2::new(0.0, -20.0))
+            .title_bar(false)
+            .resizable(false)
+            .frame(egui::Frame::window(&ctx.style()).inner_margin(egui::Margin::symmetric(15, 10)))
+            .show(ctx, |ui| {
+                ui.heading("VM halted");
+                ui.label(err.to_string());
+            });
+    }
+
+    pub fn draw_debugger(
+        &mut self,
+        egui_renderer: &EguiRenderer,
+        vm: &mut Vm,
+        debugger: &mut Debugger,
+    ) {
+        self.debugger.draw(egui_renderer, vm, debugger);
+    }
+
+    fn save_profile(&self) {
+        if let Err(err) = self.settings.write().unwrap().save_profile() {
+            eprintln!("saving settings for the ROM: {}", err);
+        }
     }
 
     pub fn update_settings(&mut self) {
@@ -142,11 +314,29 @@ impl Ui {
         let mut settings = self.settings.write().unwrap();
         settings.fg_color = self.fg_color;
         settings.bg_color = self.bg_color;
+        settings.plane2_color = self.plane2_color;
+        settings.overlap_color = self.overlap_color;
         settings.ticks_per_frame = self.ticks_per_frame;
+        settings.quirks = self.quirks;
+        settings.rewind_seconds = self.rewind_seconds;
         settings.beep_freq = self.beep_freqency;
         settings.window_has_shadow = self.window_has_shadow;
+        settings.borderless = self.borderless;
         settings.pp_enabled = self.pp_enabled;
         settings.sepia_amount = self.sepia_amount;
         settings.scale_mode = self.scale_mode;
+        self.unsaved = true;
+    }
+
+    // Writing on every change would be a write a frame while dragging, so the
+    // file waits until the mouse is released or the window closes.
+    pub fn save_settings(&mut self) {
+        if !std::mem::take(&mut self.unsaved) {
+            return;
+        }
+
+        if let Err(err) = self.settings.write().unwrap().save() {
+            eprintln!("saving settings: {}", err);
+        }
     }
 }
@@ -0,0 +1,359 @@
+use egui::{Color32, RichText, Sense};
+
+use crate::debugger::{self, Debugger};
+use crate::egui::EguiRenderer;
+use crate::vm::{self, Vm, instruction};
+
+// instructions shown around pc
+const DISASM_ROWS: usize = 24;
+const HEX_COLUMNS: usize = 16;
+
+const PC_COLOR: Color32 = Color32::from_rgb(0xff, 0x5c, 0x8a);
+const FONT_COLOR: Color32 = Color32::from_rgb(0x8c, 0x9e, 0xff);
+const ROM_COLOR: Color32 = Color32::from_rgb(0x9c, 0xd6, 0x8c);
+const SPRITE_COLOR: Color32 = Color32::from_rgb(0xff, 0xb8, 0x4a);
+
+// Registers, call stack, disassembly and memory windows. They edit the Vm and
+// Debugger in place, App hands both over for the duration of a frame.
+#[derive(Debug, Default)]
+pub struct DebuggerUi {
+    // byte being edited in the memory view
+    selected: Option<usize>,
+    goto: String,
+    scroll_to: Option<usize>,
+}
+
+impl DebuggerUi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn draw(&mut self, egui_renderer: &EguiRenderer, vm: &mut Vm, debugger: &mut Debugger) {
+        let ctx = egui_renderer.context();
+
+        egui::Window::new("Registers")
+            .default_pos(egui::pos2(20.0, 20.0))
+            .resizable(false)
+            .show(ctx, |ui| draw_registers(ui, vm, debugger));
+
+        egui::Window::new("Stack")
+            .default_pos(egui::pos2(20.0, 420.0))
+            .resizable(false)
+            .show(ctx, |ui| draw_stack(ui, vm));
+
+        egui::Window::new("Disassembly")
+            .default_pos(egui::pos2(260.0, 20.0))
+            .resizable(false)
+            .show(ctx, |ui| draw_disassembly(ui, vm, debugger));
+
+        egui::Window::new("Memory")
+            .default_pos(egui::pos2(560.0, 20.0))
+            .resizable(false)
+            .show(ctx, |ui| self.draw_memory(ui, vm, debugger));
+
+        if !debugger.monitors().is_empty() {
+            egui::Window::new("Monitors")
+                .default_pos(egui::pos2(260.0, 560.0))
+                .resizable(false)
+                .show(ctx, |ui| draw_monitors(ui, vm, debugger));
+        }
+    }
+
+    fn draw_memory(&mut self, ui: &mut egui::Ui, vm: &mut Vm, debugger: &mut Debugger) {
+        ui.horizontal(|ui| {
+            ui.label("Go to");
+            let response = ui.add(egui::TextEdit::singleline(&mut self.goto).desired_width(60.0));
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if let (true, Ok(addr)) = (submitted, debugger::parse_addr(&self.goto)) {
+                self.jump_to(addr as usize);
+            }
+
+            if ui.button("PC").clicked() {
+                self.jump_to(vm.pc() as usize);
+            }
+
+            if ui.button("I").clicked() {
+                self.jump_to(vm.ireg() as usize);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("font").color(FONT_COLOR));
+            ui.label(RichText::new("rom").color(ROM_COLOR));
+            ui.label(RichText::new("last sprite").color(SPRITE_COLOR));
+            ui.label(RichText::new("pc").color(PC_COLOR));
+        });
+
+        if let Some(addr) = self.selected {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{:04x}", addr));
+
+                let mut val = vm.mem()[addr];
+                if ui
+                    .add(egui::DragValue::new(&mut val).hexadecimal(2, false, false))
+                    .changed()
+                {
+                    vm.set_mem(addr, val);
+                    debugger.set_edited();
+                }
+            });
+        }
+
+        ui.separator();
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let rows = vm.mem().len() / HEX_COLUMNS;
+
+        let mut area = egui::ScrollArea::vertical()
+            .max_height(360.0)
+            .auto_shrink([false, false]);
+        if let Some(addr) = self.scroll_to.take() {
+            let spacing = ui.spacing().item_spacing.y;
+            area =
+                area.vertical_scroll_offset((addr / HEX_COLUMNS) as f32 * (row_height + spacing));
+        }
+
+        area.show_rows(ui, row_height, rows, |ui, visible| {
+            for row in visible {
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 5.0;
+
+                    let base = row * HEX_COLUMNS;
+                    ui.monospace(RichText::new(format!("{:04x}", base)).weak());
+
+                    for addr in base..base + HEX_COLUMNS {
+                        let mut text = RichText::new(format!("{:02x}", vm.mem()[addr])).monospace();
+                        if let Some(color) = byte_color(vm, addr) {
+                            text = text.color(color);
+                        }
+                        if self.selected == Some(addr) {
+                            text = text.underline();
+                        }
+
+                        if ui
+                            .add(egui::Label::new(text).sense(Sense::click()))
+                            .clicked()
+                        {
+                            self.selected = Some(addr);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    fn jump_to(&mut self, addr: usize) {
+        if addr < vm::MEM_SIZE {
+            self.selected = Some(addr);
+            self.scroll_to = Some(addr);
+        }
+    }
+}
+
+fn draw_registers(ui: &mut egui::Ui, vm: &mut Vm, debugger: &mut Debugger) {
+    ui.horizontal(|ui| {
+        let label = if debugger.is_paused() {
+            "Continue"
+        } else {
+            "Pause"
+        };
+        if ui.button(label).clicked() {
+            debugger.toggle_pause();
+        }
+
+        if ui.button("Step").clicked() {
+            debugger.step_into();
+        }
+
+        if ui.button("Over").clicked() {
+            debugger.step_over(vm);
+        }
+
+        if ui
+            .add_enabled(vm.sp() > 0, egui::Button::new("Out"))
+            .clicked()
+        {
+            debugger.step_out(vm);
+        }
+    });
+
+    ui.separator();
+
+    egui::Grid::new("registers")
+        .num_columns(4)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            for idx in 0..16 {
+                ui.monospace(format!("V{:X}", idx));
+
+                let mut val = vm.reg()[idx];
+                if ui
+                    .add(egui::DragValue::new(&mut val).hexadecimal(2, false, false))
+                    .changed()
+                {
+                    vm.set_reg(idx, val);
+                    debugger.set_edited();
+                }
+
+                if idx % 2 == 1 {
+                    ui.end_row();
+                }
+            }
+        });
+
+    ui.separator();
+
+    egui::Grid::new("special_registers")
+        .num_columns(4)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            ui.monospace("PC");
+            let mut pc = vm.pc();
+            if ui
+                .add(egui::DragValue::new(&mut pc).hexadecimal(3, false, false))
+                .changed()
+            {
+                vm.set_pc(pc);
+                debugger.set_edited();
+            }
+
+            ui.monospace("I");
+            let mut ireg = vm.ireg();
+            if ui
+                .add(egui::DragValue::new(&mut ireg).hexadecimal(3, false, false))
+                .changed()
+            {
+                vm.set_ireg(ireg);
+                debugger.set_edited();
+            }
+            ui.end_row();
+
+            ui.monospace("DT");
+            let mut dt = vm.dt();
+            if ui.add(egui::DragValue::new(&mut dt)).changed() {
+                vm.set_dt(dt);
+                debugger.set_edited();
+            }
+
+            ui.monospace("ST");
+            let mut st = vm.st();
+            if ui.add(egui::DragValue::new(&mut st)).changed() {
+                vm.set_st(st);
+                debugger.set_edited();
+            }
+            ui.end_row();
+        });
+}
+
+fn draw_stack(ui: &mut egui::Ui, vm: &Vm) {
+    let sp = vm.sp() as usize;
+    if sp == 0 {
+        ui.label(RichText::new("empty").weak());
+        return;
+    }
+
+    // innermost call first, entries hold the address after the CALL
+    for (depth, &ret) in vm.stack()[..sp].iter().enumerate().rev() {
+        ui.monospace(format!(
+            "#{:<2} 0x{:03x} from 0x{:03x}",
+            depth,
+            ret,
+            ret.wrapping_sub(2)
+        ));
+    }
+}
+
+fn draw_disassembly(ui: &mut egui::Ui, vm: &Vm, debugger: &mut Debugger) {
+    let pc = vm.pc() as usize;
+    // keep pc's alignment so the rows decode the same instructions the VM will
+    let start = if pc >= DISASM_ROWS {
+        pc - DISASM_ROWS
+    } else {
+        pc % 2
+    };
+
+    egui::Grid::new("disassembly")
+        .num_columns(4)
+        .striped(true)
+        .show(ui, |ui| {
+            for row in 0..DISASM_ROWS {
+                let addr = start + row * 2;
+                let Some(bytes) = vm.mem().get(addr..addr + 2) else {
+                    break;
+                };
+                let opcode = u16::from_be_bytes([bytes[0], bytes[1]]);
+                let next = vm
+                    .mem()
+                    .get(addr + 2..addr + 4)
+                    .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+
+                let marker = if debugger.has_breakpoint(addr as u16) {
+                    RichText::new("●").color(PC_COLOR)
+                } else {
+                    RichText::new("○").weak()
+                };
+                if ui
+                    .add(egui::Label::new(marker).sense(Sense::click()))
+                    .on_hover_text("Toggle breakpoint")
+                    .clicked()
+                {
+                    debugger.toggle_breakpoint(addr as u16);
+                }
+
+                let color = if addr == pc {
+                    PC_COLOR
+                } else {
+                    ui.visuals().text_color()
+                };
+                ui.label(
+                    RichText::new(format!("{:03x}", addr))
+                        .monospace()
+                        .color(color),
+                );
+                ui.label(RichText::new(format!("{:04x}", opcode)).monospace().weak());
+                ui.label(
+                    RichText::new(instruction::decode(opcode).display_with(next))
+                        .monospace()
+                        .color(color),
+                );
+                ui.end_row();
+            }
+        });
+}
+
+fn draw_monitors(ui: &mut egui::Ui, vm: &Vm, debugger: &Debugger) {
+    egui::Grid::new("monitors")
+        .num_columns(3)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            for monitor in debugger.monitors() {
+                let start = monitor.addr as usize;
+                let end = (start + monitor.len as usize).min(vm::MEM_SIZE);
+                let bytes: Vec<String> = vm.mem()[start..end]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+
+                ui.monospace(&monitor.name);
+                ui.monospace(RichText::new(format!("{:04x}", start)).weak());
+                ui.monospace(bytes.join(" "));
+                ui.end_row();
+            }
+        });
+}
+
+fn byte_color(vm: &Vm, addr: usize) -> Option<Color32> {
+    let pc = vm.pc() as usize;
+
+    if (pc..pc + 2).contains(&addr) {
+        Some(PC_COLOR)
+    } else if vm.last_sprite().contains(&addr) {
+        Some(SPRITE_COLOR)
+    } else if vm.rom_range().contains(&addr) {
+        Some(ROM_COLOR)
+    } else if addr < vm::FONTS_END {
+        Some(FONT_COLOR)
+    } else {
+        None
+    }
+}
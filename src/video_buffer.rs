@@ -1,64 +1,187 @@
 pub const CHIP8_WIDTH: usize = 64;
 pub const CHIP8_HEIGHT: usize = 32;
 
-const BUFFER_WORDS: usize = CHIP8_WIDTH * CHIP8_HEIGHT / 32;
+pub const SCHIP_WIDTH: usize = 128;
+pub const SCHIP_HEIGHT: usize = 64;
+
+// XO-CHIP bitplanes, a pixel's colour index is (plane 2 bit << 1) | plane 1 bit
+pub const PLANES: usize = 2;
+
+const PLANE_WORDS: usize = SCHIP_WIDTH * SCHIP_HEIGHT / 32;
+const BUFFER_WORDS: usize = PLANE_WORDS * PLANES;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct VideoBuffer {
+    width: u32,
+    height: u32,
     pixels: [u32; BUFFER_WORDS],
 }
 
 impl Default for VideoBuffer {
     fn default() -> Self {
         Self {
+            width: CHIP8_WIDTH as u32,
+            height: CHIP8_HEIGHT as u32,
             pixels: [0; BUFFER_WORDS],
         }
     }
 }
 
 impl VideoBuffer {
-    pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
-        let idx = y * CHIP8_WIDTH + x;
-        let word = idx / 32;
-        let bit = idx % 32;
-        if value {
-            self.pixels[word] |= 1 << bit;
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width as usize == SCHIP_WIDTH
+    }
+
+    // switching resolution clears the screen, like SCHIP 00FE/00FF do
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            (SCHIP_WIDTH, SCHIP_HEIGHT)
         } else {
-            self.pixels[word] &= !(1 << bit);
-        }
+            (CHIP8_WIDTH, CHIP8_HEIGHT)
+        };
+
+        self.width = width as u32;
+        self.height = height as u32;
+        self.clear();
+    }
+
+    fn locate(&self, plane: usize, x: usize, y: usize) -> (usize, usize) {
+        let idx = y * self.width() + x;
+        (plane * PLANE_WORDS + idx / 32, idx % 32)
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
+        self.set_plane_pixel(0, x, y, value);
     }
 
     pub fn get_pixel(&self, x: usize, y: usize) -> bool {
-        let idx = y * CHIP8_WIDTH + x;
-        let word = idx / 32;
-        let bit = idx % 32;
-        (self.pixels[word] >> bit) & 1 != 0
+        self.get_plane_pixel(0, x, y)
     }
 
     pub fn toggle_pixel(&mut self, x: usize, y: usize) {
-        let idx = y * CHIP8_WIDTH + x;
-        let word = idx / 32;
-        let bit = idx % 32;
+        let (word, bit) = self.locate(0, x, y);
         self.pixels[word] ^= 1 << bit;
     }
 
     pub fn xor_pixel(&mut self, x: usize, y: usize, value: bool) -> bool {
-        let idx = y * CHIP8_WIDTH + x;
-        let word = idx / 32;
-        let bit = idx % 32;
+        self.xor_plane_pixel(0, x, y, value)
+    }
+
+    pub fn set_plane_pixel(&mut self, plane: usize, x: usize, y: usize, value: bool) {
+        let (word, bit) = self.locate(plane, x, y);
+        if value {
+            self.pixels[word] |= 1 << bit;
+        } else {
+            self.pixels[word] &= !(1 << bit);
+        }
+    }
+
+    pub fn get_plane_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        let (word, bit) = self.locate(plane, x, y);
+        (self.pixels[word] >> bit) & 1 != 0
+    }
+
+    pub fn xor_plane_pixel(&mut self, plane: usize, x: usize, y: usize, value: bool) -> bool {
+        let (word, bit) = self.locate(plane, x, y);
         let current = (self.pixels[word] >> bit) & 1 != 0;
         self.pixels[word] ^= (value as u32) << bit;
         current
     }
 
+    // palette index of the pixel, 0 is background
+    pub fn color_index(&self, x: usize, y: usize) -> u8 {
+        (0..PLANES).fold(0, |acc, plane| {
+            acc | ((self.get_plane_pixel(plane, x, y) as u8) << plane)
+        })
+    }
+
     pub fn clear(&mut self) {
         self.pixels = [0; BUFFER_WORDS];
     }
 
+    pub fn clear_planes(&mut self, mask: u8) {
+        for plane in selected(mask) {
+            self.pixels[plane * PLANE_WORDS..(plane + 1) * PLANE_WORDS].fill(0);
+        }
+    }
+
+    pub fn scroll_down(&mut self, mask: u8, n: usize) {
+        for plane in selected(mask) {
+            for y in (0..self.height()).rev() {
+                for x in 0..self.width() {
+                    let value = y >= n && self.get_plane_pixel(plane, x, y - n);
+                    self.set_plane_pixel(plane, x, y, value);
+                }
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, mask: u8, n: usize) {
+        for plane in selected(mask) {
+            for y in 0..self.height() {
+                for x in 0..self.width() {
+                    let value = y + n < self.height() && self.get_plane_pixel(plane, x, y + n);
+                    self.set_plane_pixel(plane, x, y, value);
+                }
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, mask: u8, n: usize) {
+        for plane in selected(mask) {
+            for y in 0..self.height() {
+                for x in 0..self.width() {
+                    let value = x + n < self.width() && self.get_plane_pixel(plane, x + n, y);
+                    self.set_plane_pixel(plane, x, y, value);
+                }
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, mask: u8, n: usize) {
+        for plane in selected(mask) {
+            for y in 0..self.height() {
+                for x in (0..self.width()).rev() {
+                    let value = x >= n && self.get_plane_pixel(plane, x - n, y);
+                    self.set_plane_pixel(plane, x, y, value);
+                }
+            }
+        }
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         bytemuck::bytes_of(self)
     }
+
+    // width, height and the pixel words, for save states
+    pub fn words(&self) -> impl Iterator<Item = u32> + '_ {
+        [self.width, self.height]
+            .into_iter()
+            .chain(self.pixels.iter().copied())
+    }
+
+    pub fn from_words(words: &[u32]) -> Option<Self> {
+        let vb: Self = bytemuck::try_pod_read_unaligned(bytemuck::cast_slice(words)).ok()?;
+        let valid = matches!(
+            (vb.width(), vb.height()),
+            (CHIP8_WIDTH, CHIP8_HEIGHT) | (SCHIP_WIDTH, SCHIP_HEIGHT)
+        );
+
+        valid.then_some(vb)
+    }
+}
+
+pub fn selected(mask: u8) -> impl Iterator<Item = usize> {
+    (0..PLANES).filter(move |plane| mask & (1 << plane) != 0)
 }
 
 #[cfg(test)]
@@ -91,4 +214,36 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_hires_scroll() {
+        let mut buffer = VideoBuffer::default();
+        buffer.set_hires(true);
+        assert_eq!(buffer.width(), SCHIP_WIDTH);
+
+        buffer.set_pixel(SCHIP_WIDTH - 1, 0, true);
+        buffer.scroll_down(0b01, 4);
+        assert!(buffer.get_pixel(SCHIP_WIDTH - 1, 4));
+        assert!(!buffer.get_pixel(SCHIP_WIDTH - 1, 0));
+
+        buffer.scroll_left(0b01, 4);
+        assert!(buffer.get_pixel(SCHIP_WIDTH - 5, 4));
+
+        buffer.scroll_right(0b01, 8);
+        assert!(!buffer.get_pixel(SCHIP_WIDTH - 5, 4));
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut buffer = VideoBuffer::default();
+        buffer.set_plane_pixel(0, 3, 3, true);
+        buffer.set_plane_pixel(1, 3, 3, true);
+        buffer.set_plane_pixel(1, 4, 3, true);
+        assert_eq!(buffer.color_index(3, 3), 3);
+        assert_eq!(buffer.color_index(4, 3), 2);
+
+        buffer.clear_planes(0b10);
+        assert_eq!(buffer.color_index(3, 3), 1);
+        assert_eq!(buffer.color_index(4, 3), 0);
+    }
 }
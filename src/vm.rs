@@ -1,15 +1,39 @@
+pub mod access;
+pub mod error;
+pub mod instruction;
+pub mod quirks;
+pub mod rng;
 pub mod sprites;
+pub mod state;
 
-use crate::video_buffer::{CHIP8_HEIGHT, CHIP8_WIDTH, VideoBuffer};
+use std::ops::Range;
 
-use sprites::SPRITES;
+use crate::romdb::{self, RomInfo};
+use crate::video_buffer::{self, VideoBuffer};
+
+pub use access::{AccessKind, MemAccess};
+pub use error::VmError;
+pub use instruction::Instruction;
+pub use quirks::{Quirks, QuirksPreset};
+use rng::Rng;
+use sprites::{BIG_SPRITES, SPRITES};
+pub use state::StateError;
+use state::{StateReader, StateWriter};
+
+const BIG_SPRITES_ADDR: usize = SPRITES.len();
+
+// both built-in fonts live at the start of memory
+pub const FONTS_END: usize = BIG_SPRITES_ADDR + BIG_SPRITES.len();
+
+// XO-CHIP extends the address space to 64KB
+pub const MEM_SIZE: usize = 0x10000;
 
 #[derive(Debug)]
 pub struct Vm {
     pub vb: VideoBuffer,
 
     pc: u16,
-    mem: [u8; 4096],
+    mem: [u8; MEM_SIZE],
 
     reg: [u8; 16],
     ireg: u16,
@@ -20,19 +44,39 @@ pub struct Vm {
     kb: [bool; 16],
 
     dt: u8,
-    pub st: u8,
+    st: u8,
+
+    rpl: [u8; 16],
+    exited: bool,
+    planes: u8,
+
+    quirks: Quirks,
+
+    // Cxkk starts over from the seed whenever the VM is reset
+    seed: u64,
+    rng: Rng,
+
+    track_accesses: bool,
+    accesses: Vec<MemAccess>,
+
+    // what the debugger highlights in memory
+    rom_len: usize,
+    last_sprite: Range<usize>,
 
     rom_path: Option<String>,
+    rom_hash: Option<String>,
+    rom_info: Option<&'static RomInfo>,
 }
 
 impl Vm {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        let seed = rand::random();
         let mut vm = Self {
             vb: VideoBuffer::default(),
 
             pc: 0x200,
-            mem: [0; 4096],
+            mem: [0; MEM_SIZE],
 
             reg: [0; 16],
             ireg: 0,
@@ -45,18 +89,50 @@ impl Vm {
             dt: 0,
             st: 0,
 
+            rpl: [0; 16],
+            exited: false,
+            planes: 0b01,
+
+            quirks: Quirks::default(),
+
+            seed,
+            rng: Rng::new(seed),
+
+            track_accesses: false,
+            accesses: Vec::new(),
+
+            rom_len: 0,
+            last_sprite: 0..0,
+
             rom_path: None,
+            rom_hash: None,
+            rom_info: None,
         };
 
-        vm.mem[..SPRITES.len()].copy_from_slice(&SPRITES);
+        vm.load_fonts();
 
         vm
     }
 
+    // A Vm with `program` at 0x200 and no ROM file behind it, for tests and
+    // tools. Panics if the program doesn't fit in memory.
+    pub fn from_bytes(program: &[u8]) -> Self {
+        let mut vm = Self::new();
+        vm.load_bin(program);
+        vm
+    }
+
+    fn load_fonts(&mut self) {
+        self.mem[..SPRITES.len()].copy_from_slice(&SPRITES);
+        self.mem[BIG_SPRITES_ADDR..(BIG_SPRITES_ADDR + BIG_SPRITES.len())]
+            .copy_from_slice(&BIG_SPRITES);
+    }
+
     pub fn reset(&mut self) {
+        self.vb = VideoBuffer::default();
         self.pc = 0x200;
-        self.mem = [0; 4096];
-        self.mem[..SPRITES.len()].copy_from_slice(&SPRITES);
+        self.mem = [0; MEM_SIZE];
+        self.load_fonts();
         self.reg = [0; 16];
         self.ireg = 0;
         self.sp = 0;
@@ -64,41 +140,279 @@ impl Vm {
         self.kb = [false; 16];
         self.dt = 0;
         self.st = 0;
+        self.rpl = [0; 16];
+        self.exited = false;
+        self.planes = 0b01;
+        self.rng = Rng::new(self.seed);
+        self.rom_len = 0;
+        self.last_sprite = 0..0;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn reg(&self) -> &[u8; 16] {
+        &self.reg
+    }
+
+    pub fn ireg(&self) -> u16 {
+        self.ireg
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    // only the first sp entries are in use
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+
+    // SCHIP RPL user flags, Fx75/Fx85
+    pub fn rpl(&self) -> &[u8; 16] {
+        &self.rpl
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    pub fn mem(&self) -> &[u8] {
+        &self.mem
+    }
+
+    // setters for the debugger, they bypass the memory access hook
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn set_reg(&mut self, idx: usize, val: u8) {
+        self.reg[idx] = val;
+    }
+
+    pub fn set_ireg(&mut self, ireg: u16) {
+        self.ireg = ireg;
+    }
+
+    pub fn set_dt(&mut self, dt: u8) {
+        self.dt = dt;
+    }
+
+    pub fn set_st(&mut self, st: u8) {
+        self.st = st;
+    }
+
+    pub fn set_mem(&mut self, addr: usize, val: u8) {
+        if let Some(cell) = self.mem.get_mut(addr) {
+            *cell = val;
+        }
+    }
+
+    pub fn rom_range(&self) -> Range<usize> {
+        0x200..(0x200 + self.rom_len)
+    }
+
+    // bytes read by the most recent Dxyn
+    pub fn last_sprite(&self) -> Range<usize> {
+        self.last_sprite.clone()
+    }
+
+    // picked from OS entropy unless set
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // also restarts the random numbers, so set it before running anything
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // When enabled every memory read and write of the last tick is reported through
+    // mem_accesses, that's how the debugger's watchpoints see what an instruction touched.
+    pub fn set_track_accesses(&mut self, track: bool) {
+        self.track_accesses = track;
+        self.accesses.clear();
+    }
+
+    pub fn mem_accesses(&self) -> &[MemAccess] {
+        &self.accesses
+    }
+
+    // set by SCHIP 00FD, the program has finished and won't tick anymore
+    pub fn exited(&self) -> bool {
+        self.exited
     }
 
     pub fn load_rom(&mut self, rom_path: String) -> Result<(), std::io::Error> {
         let rom_bytes = std::fs::read(rom_path.clone())?;
+        self.load_rom_bytes(rom_path, &rom_bytes)
+    }
+
+    // for ROMs that don't come straight from a file, like compiled Octo sources
+    pub fn load_rom_bytes(
+        &mut self,
+        rom_path: String,
+        rom_bytes: &[u8],
+    ) -> Result<(), std::io::Error> {
+        if rom_bytes.len() > self.mem.len() - 0x200 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("ROM is too large: {} bytes", rom_bytes.len()),
+            ));
+        }
 
         self.reset();
 
         self.rom_path = Some(rom_path);
-        self.load_bin(&rom_bytes);
+        let hash = sha1_smol::Sha1::from(rom_bytes).digest().to_string();
+        self.rom_info = romdb::lookup(&hash);
+        self.rom_hash = Some(hash);
+        self.load_bin(rom_bytes);
         Ok(())
     }
 
+    pub fn rom_path(&self) -> Option<&str> {
+        self.rom_path.as_deref()
+    }
+
+    // SHA-1 of the loaded ROM, hex encoded
+    pub fn rom_hash(&self) -> Option<&str> {
+        self.rom_hash.as_deref()
+    }
+
+    // the ROM's entry in the built-in database, if it has one
+    pub fn rom_info(&self) -> Option<&'static RomInfo> {
+        self.rom_info
+    }
+
     fn load_bin(&mut self, rom: &[u8]) {
         self.mem[0x200..(0x200 + rom.len())].copy_from_slice(rom);
+        self.rom_len = rom.len();
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.u16(self.pc);
+        w.u16(self.ireg);
+        w.u16(self.sp);
+        for val in self.stack {
+            w.u16(val);
+        }
+        w.bytes(&self.reg);
+
+        w.u8(self.dt);
+        w.u8(self.st);
+        w.bytes(&self.rpl);
+        w.u8(self.exited as u8);
+        w.u8(self.planes);
+        w.u64(self.rng.state());
+        for pressed in self.kb {
+            w.u8(pressed as u8);
+        }
+
+        w.bytes(&self.mem);
+        for word in self.vb.words() {
+            w.u32(word);
+        }
+
+        w.finish()
+    }
+
+    // the VM is left untouched if the state can't be loaded
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data)?;
+
+        let pc = r.u16()?;
+        let ireg = r.u16()?;
+        let sp = r.u16()?;
+        if sp as usize > self.stack.len() {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+
+        let mut stack = [0; 16];
+        for val in stack.iter_mut() {
+            *val = r.u16()?;
+        }
+        let reg = r.bytes(16)?;
+
+        let dt = r.u8()?;
+        let st = r.u8()?;
+        let rpl = r.bytes(16)?;
+        let exited = r.u8()? != 0;
+        let planes = r.u8()? & 0b11;
+        let rng = Rng::new(r.u64()?);
+        let mut kb = [false; 16];
+        for pressed in kb.iter_mut() {
+            *pressed = r.u8()? != 0;
+        }
+
+        let mem = r.bytes(MEM_SIZE)?;
+        let mut words = vec![0; std::mem::size_of::<VideoBuffer>() / 4];
+        for word in words.iter_mut() {
+            *word = r.u32()?;
+        }
+        let vb = VideoBuffer::from_words(&words).ok_or(StateError::Invalid("video buffer"))?;
+        r.finish()?;
+
+        self.pc = pc;
+        self.ireg = ireg;
+        self.sp = sp;
+        self.stack = stack;
+        self.reg.copy_from_slice(reg);
+        self.dt = dt;
+        self.st = st;
+        self.rpl.copy_from_slice(rpl);
+        self.exited = exited;
+        self.planes = planes;
+        self.rng = rng;
+        self.kb = kb;
+        self.mem.copy_from_slice(mem);
+        self.vb = vb;
+
+        Ok(())
     }
 
-    pub fn tick(&mut self) {
+    pub fn tick(&mut self) -> Result<(), VmError> {
+        if self.exited {
+            return Ok(());
+        }
+
+        self.accesses.clear();
+
+        if self.pc as usize + 1 >= self.mem.len() {
+            return Err(VmError::PcOutOfBounds { pc: self.pc });
+        }
+
         let left = self.mem[self.pc as usize] as u16;
         let right = self.mem[(self.pc + 1) as usize] as u16;
 
         let opcode = (left << 8) | right;
-        self.pc += 2;
-
-        let a = (opcode >> 12) & 0x0f;
-        let b = (opcode >> 8) & 0x0f;
-        let c = (opcode >> 4) & 0x0f;
-        let d = opcode & 0x0f;
+        self.pc = self.pc.wrapping_add(2);
 
         // http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
-        match (a, b, c, d) {
+        match instruction::decode(opcode) {
             /*
                 00E0 - CLS
                 Clear the display.
+
+                In XO-CHIP, only the selected planes are cleared.
             */
-            (0x00, 0x00, 0x0e, 0x00) => self.vb.clear(),
+            Instruction::Cls => self.vb.clear_planes(self.planes),
 
             /*
                 00EE - RET
@@ -106,7 +420,57 @@ impl Vm {
 
                 The interpreter sets the program counter to the address at the top of the stack, then subtracts 1 from the stack pointer.
             */
-            (0x00, 0x00, 0x0e, 0x0e) => self.pc = self.pop(),
+            Instruction::Ret => self.pc = self.pop()?,
+
+            /*
+                00Cn - SCD nibble (SCHIP)
+                Scroll display down n lines.
+
+                In XO-CHIP, only the selected planes are scrolled.
+            */
+            Instruction::Scd(n) => self.vb.scroll_down(self.planes, n as usize),
+
+            /*
+                00Dn - SCU nibble (XO-CHIP)
+                Scroll display up n lines.
+
+                In XO-CHIP, only the selected planes are scrolled.
+            */
+            Instruction::Scu(n) => self.vb.scroll_up(self.planes, n as usize),
+
+            /*
+                00FB - SCR (SCHIP)
+                Scroll display right by 4 pixels.
+
+                In XO-CHIP, only the selected planes are scrolled.
+            */
+            Instruction::Scr => self.vb.scroll_right(self.planes, 4),
+
+            /*
+                00FC - SCL (SCHIP)
+                Scroll display left by 4 pixels.
+
+                In XO-CHIP, only the selected planes are scrolled.
+            */
+            Instruction::Scl => self.vb.scroll_left(self.planes, 4),
+
+            /*
+                00FD - EXIT (SCHIP)
+                Exit the interpreter.
+            */
+            Instruction::Exit => self.exited = true,
+
+            /*
+                00FE - LOW (SCHIP)
+                Disable high resolution mode, back to 64x32.
+            */
+            Instruction::Low => self.vb.set_hires(false),
+
+            /*
+                00FF - HIGH (SCHIP)
+                Enable high resolution mode, 128x64.
+            */
+            Instruction::High => self.vb.set_hires(true),
 
             /*
                 1nnn - JP addr
@@ -114,10 +478,7 @@ impl Vm {
 
                 The interpreter sets the program counter to nnn.
             */
-            (0x01, _, _, _) => {
-                let addr = opcode & 0x0fff;
-                self.pc = addr;
-            }
+            Instruction::Jp(addr) => self.pc = addr,
 
             /*
                 2nnn - CALL addr
@@ -125,9 +486,8 @@ impl Vm {
 
                 The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC is then set to nnn.
             */
-            (0x02, _, _, _) => {
-                let addr = opcode & 0x0fff;
-                self.push(self.pc);
+            Instruction::Call(addr) => {
+                self.push(self.pc)?;
                 self.pc = addr;
             }
 
@@ -137,12 +497,9 @@ impl Vm {
 
                 The interpreter compares register Vx to kk, and if they are equal, increments the program counter by 2.
             */
-            (0x03, _, _, _) => {
-                let reg_val = self.reg[b as usize];
-                let val = (opcode & 0xff) as u8;
-
-                if reg_val == val {
-                    self.pc += 2;
+            Instruction::SeImm { x, kk } => {
+                if self.reg[x as usize] == kk {
+                    self.skip();
                 }
             }
 
@@ -152,12 +509,9 @@ impl Vm {
 
                 The interpreter compares register Vx to kk, and if they are not equal, increments the program counter by 2.
             */
-            (0x04, _, _, _) => {
-                let reg_val = self.reg[b as usize];
-                let val = (opcode & 0xff) as u8;
-
-                if reg_val != val {
-                    self.pc += 2;
+            Instruction::SneImm { x, kk } => {
+                if self.reg[x as usize] != kk {
+                    self.skip();
                 }
             }
 
@@ -167,12 +521,34 @@ impl Vm {
 
                 The interpreter compares register Vx to register Vy, and if they are equal, increments the program counter by 2.
             */
-            (0x05, _, _, 0x00) => {
-                let reg_x_val = self.reg[b as usize];
-                let reg_y_val = self.reg[c as usize];
+            Instruction::SeReg { x, y } => {
+                let reg_x_val = self.reg[x as usize];
+                let reg_y_val = self.reg[y as usize];
 
                 if reg_x_val == reg_y_val {
-                    self.pc += 2;
+                    self.skip();
+                }
+            }
+
+            /*
+                5xy2 - SAVE Vx - Vy (XO-CHIP)
+                Store registers Vx through Vy in memory starting at location I, I is not changed.
+
+                If x > y the registers are stored in reverse order.
+            */
+            Instruction::Save { x, y } => {
+                for (offset, idx) in reg_range(x, y).enumerate() {
+                    self.write_mem(self.ireg as usize + offset, self.reg[idx])?;
+                }
+            }
+
+            /*
+                5xy3 - LOAD Vx - Vy (XO-CHIP)
+                Read registers Vx through Vy from memory starting at location I, I is not changed.
+            */
+            Instruction::Load { x, y } => {
+                for (offset, idx) in reg_range(x, y).enumerate() {
+                    self.reg[idx] = self.read_mem(self.ireg as usize + offset)?;
                 }
             }
 
@@ -182,9 +558,8 @@ impl Vm {
 
                 The interpreter puts the value kk into register Vx.
             */
-            (0x06, _, _, _) => {
-                let val = (opcode & 0xff) as u8;
-                self.reg[b as usize] = val;
+            Instruction::LdImm { x, kk } => {
+                self.reg[x as usize] = kk;
             }
 
             /*
@@ -193,10 +568,9 @@ impl Vm {
 
                 Adds the value kk to the value of register Vx, then stores the result in Vx.
             */
-            (0x07, _, _, _) => {
-                let val = (opcode & 0xff) as u8;
-                let idx = b as usize;
-                self.reg[idx] = self.reg[idx].wrapping_add(val);
+            Instruction::AddImm { x, kk } => {
+                let idx = x as usize;
+                self.reg[idx] = self.reg[idx].wrapping_add(kk);
             }
 
             /*
@@ -205,9 +579,9 @@ impl Vm {
 
                 Stores the value of register Vy in register Vx.
             */
-            (0x08, _, _, 0x00) => {
-                let vy_val = self.reg[c as usize];
-                self.reg[b as usize] = vy_val;
+            Instruction::LdReg { x, y } => {
+                let vy_val = self.reg[y as usize];
+                self.reg[x as usize] = vy_val;
             }
             /*
                 8xy1 - OR Vx, Vy
@@ -217,8 +591,11 @@ impl Vm {
                 A bitwise OR compares the corrseponding bits from two values, and if either bit is 1,
                 then the same bit in the result is also 1. Otherwise, it is 0.
             */
-            (0x08, _, _, 0x01) => {
-                self.reg[b as usize] |= self.reg[c as usize];
+            Instruction::Or { x, y } => {
+                self.reg[x as usize] |= self.reg[y as usize];
+                if self.quirks.vf_reset {
+                    self.reg[0x0f] = 0;
+                }
             }
 
             /*
@@ -229,8 +606,11 @@ impl Vm {
                 A bitwise AND compares the corrseponding bits from two values, and if both bits are 1,
                 then the same bit in the result is also 1. Otherwise, it is 0.
             */
-            (0x08, _, _, 0x02) => {
-                self.reg[b as usize] &= self.reg[c as usize];
+            Instruction::And { x, y } => {
+                self.reg[x as usize] &= self.reg[y as usize];
+                if self.quirks.vf_reset {
+                    self.reg[0x0f] = 0;
+                }
             }
 
             /*
@@ -241,8 +621,11 @@ impl Vm {
                 An exclusive OR compares the corrseponding bits from two values, and if the bits are not both the same,
                 then the corresponding bit in the result is set to 1. Otherwise, it is 0.
             */
-            (0x08, _, _, 0x03) => {
-                self.reg[b as usize] ^= self.reg[c as usize];
+            Instruction::Xor { x, y } => {
+                self.reg[x as usize] ^= self.reg[y as usize];
+                if self.quirks.vf_reset {
+                    self.reg[0x0f] = 0;
+                }
             }
 
             /*
@@ -252,9 +635,9 @@ impl Vm {
                 The values of Vx and Vy are added together. If the result is greater than 8 bits (i.e., > 255,) VF is set to 1, otherwise 0.
                 Only the lowest 8 bits of the result are kept, and stored in Vx.
             */
-            (0x08, _, _, 0x04) => {
-                let (res, carry) = self.reg[b as usize].overflowing_add(self.reg[c as usize]);
-                self.reg[b as usize] = res;
+            Instruction::AddReg { x, y } => {
+                let (res, carry) = self.reg[x as usize].overflowing_add(self.reg[y as usize]);
+                self.reg[x as usize] = res;
                 self.reg[0x0f] = carry as u8;
             }
 
@@ -264,9 +647,9 @@ impl Vm {
 
                 If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
             */
-            (0x08, _, _, 0x05) => {
-                let (res, carry) = self.reg[b as usize].overflowing_sub(self.reg[c as usize]);
-                self.reg[b as usize] = res;
+            Instruction::Sub { x, y } => {
+                let (res, carry) = self.reg[x as usize].overflowing_sub(self.reg[y as usize]);
+                self.reg[x as usize] = res;
                 self.reg[0x0f] = !carry as u8;
             }
 
@@ -275,10 +658,12 @@ impl Vm {
                 Set Vx = Vx SHR 1.
 
                 If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
+                With the shift quirk Vy is shifted and the result stored in Vx.
             */
-            (0x08, _, _, 0x06) => {
-                self.reg[0x0f] = self.reg[b as usize] & 0b1;
-                self.reg[b as usize] >>= 1;
+            Instruction::Shr { x, y } => {
+                let src = self.shift_source(x, y);
+                self.reg[x as usize] = src >> 1;
+                self.reg[0x0f] = src & 0b1;
             }
 
             /*
@@ -287,10 +672,10 @@ impl Vm {
 
                 If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
             */
-            (0x08, _, _, 0x07) => {
-                let (new, carry) = self.reg[c as usize].overflowing_sub(self.reg[b as usize]);
+            Instruction::Subn { x, y } => {
+                let (new, carry) = self.reg[y as usize].overflowing_sub(self.reg[x as usize]);
 
-                self.reg[b as usize] = new;
+                self.reg[x as usize] = new;
                 self.reg[0x0f] = !carry as u8;
             }
 
@@ -299,11 +684,12 @@ impl Vm {
                 Set Vx = Vx SHL 1.
 
                 If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
+                With the shift quirk Vy is shifted and the result stored in Vx.
             */
-            (0x08, _, _, 0x0e) => {
-                self.reg[0x0f] = self.reg[b as usize] & 0b1;
-                self.reg[0x0f] = (self.reg[b as usize] >> 7) & 1;
-                self.reg[b as usize] <<= 1;
+            Instruction::Shl { x, y } => {
+                let src = self.shift_source(x, y);
+                self.reg[x as usize] = src << 1;
+                self.reg[0x0f] = (src >> 7) & 1;
             }
 
             /*
@@ -312,9 +698,9 @@ impl Vm {
 
                 The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
             */
-            (0x09, _, _, 0x0) => {
-                if self.reg[b as usize] != self.reg[c as usize] {
-                    self.pc += 2;
+            Instruction::SneReg { x, y } => {
+                if self.reg[x as usize] != self.reg[y as usize] {
+                    self.skip();
                 }
             }
 
@@ -324,8 +710,8 @@ impl Vm {
 
                 The value of register I is set to nnn.
             */
-            (0x0a, _, _, _) => {
-                self.ireg = opcode & 0x0fff;
+            Instruction::LdI(addr) => {
+                self.ireg = addr;
             }
 
             /*
@@ -333,10 +719,16 @@ impl Vm {
                 Jump to location nnn + V0.
 
                 The program counter is set to nnn plus the value of V0.
+                With the jump quirk it is Bxnn - JP Vx, addr, nnn plus the value of Vx.
             */
-            (0x0b, _, _, _) => {
-                let addr = (self.reg[0x00] as u16) + (opcode & 0x0fff);
-                self.pc = addr;
+            Instruction::JpV0 { x, addr } => {
+                let offset = if self.quirks.jump_with_vx {
+                    self.reg[x as usize]
+                } else {
+                    self.reg[0x00]
+                };
+
+                self.pc = (offset as u16) + addr;
             }
 
             /*
@@ -346,9 +738,8 @@ impl Vm {
                 The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk.
                 The results are stored in Vx. See instruction 8xy2 for more information on AND.
             */
-            (0x0c, _, _, _) => {
-                let rnd: u8 = rand::random();
-                self.reg[b as usize] = rnd & (opcode & 0xff) as u8;
+            Instruction::Rnd { x, kk } => {
+                self.reg[x as usize] = self.rng.next_u8() & kk;
             }
 
             /*
@@ -360,34 +751,18 @@ impl Vm {
                 Sprites are XORed onto the existing screen.
                 If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0.
                 If the sprite is positioned so part of it is outside the coordinates of the display,
-                it wraps around to the opposite side of the screen.
+                it wraps around to the opposite side of the screen (or is clipped with the clip quirk).
                 See instruction 8xy3 for more information on XOR, and section 2.4,
                 Display, for more information on the Chip-8 screen and sprites.
-            */
-            (0x0d, _, _, _) => {
-                let x = self.reg[b as usize] as u16;
-                let y = self.reg[c as usize] as u16;
-
-                let n = d;
-
-                let mut flipped = false;
-                for sprite_y in 0..n {
-                    let addr = self.ireg + sprite_y;
-                    let pixels = self.mem[addr as usize];
 
-                    for sprite_x in 0..8 {
-                        let px = ((x + sprite_x) % CHIP8_WIDTH as u16) as usize;
-                        let py = ((y + sprite_y) % CHIP8_HEIGHT as u16) as usize;
-
-                        let sprite_bit = ((pixels >> (7 - sprite_x)) & 1) != 0;
-                        let was_on = self.vb.xor_pixel(px, py, sprite_bit);
-                        if was_on && sprite_bit {
-                            flipped = true;
-                        }
-                    }
-                }
+                Dxy0 - DRW Vx, Vy, 0 (SCHIP)
+                Draw a 16x16 sprite, 32 bytes starting at I, two bytes per row.
+            */
+            Instruction::Drw { x, y, n } => {
+                let px = self.reg[x as usize] as usize;
+                let py = self.reg[y as usize] as usize;
 
-                self.reg[0xf] = flipped as u8;
+                self.draw_sprite(px, py, n as usize)?;
             }
 
             /*
@@ -396,10 +771,10 @@ impl Vm {
 
                 Checks the keyboard, and if the key corresponding to the value of Vx is currently in the down position, PC is increased by 2.
             */
-            (0x0e, _, 0x09, 0x0e) => {
-                let key_num = self.reg[b as usize];
-                if self.kb[key_num as usize] {
-                    self.pc += 2;
+            Instruction::Skp(x) => {
+                let key_num = self.reg[x as usize];
+                if self.kb[(key_num & 0x0f) as usize] {
+                    self.skip();
                 }
             }
 
@@ -409,21 +784,40 @@ impl Vm {
 
                 Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up position, PC is increased by 2.
             */
-            (0x0e, _, 0x0a, 0x01) => {
-                let key_num = self.reg[b as usize];
-                if !self.kb[key_num as usize] {
-                    self.pc += 2;
+            Instruction::Sknp(x) => {
+                let key_num = self.reg[x as usize];
+                if !self.kb[(key_num & 0x0f) as usize] {
+                    self.skip();
                 }
             }
 
+            /*
+                F000 nnnn - LD I, long addr (XO-CHIP)
+                Set I = the 16-bit address stored in the next two bytes, then skip over them.
+            */
+            Instruction::LdILong => {
+                let hi = self.read_mem(self.pc as usize)? as u16;
+                let lo = self.read_mem(self.pc as usize + 1)? as u16;
+                self.ireg = (hi << 8) | lo;
+                self.pc = self.pc.wrapping_add(2);
+            }
+
+            /*
+                Fn01 - PLANE n (XO-CHIP)
+                Select the drawing planes with bitmask n.
+            */
+            Instruction::Plane(n) => {
+                self.planes = n & 0b11;
+            }
+
             /*
                 Fx07 - LD Vx, DT
                 Set Vx = delay timer value.
 
                 The value of DT is placed into Vx.
             */
-            (0x0f, _, 0x00, 0x07) => {
-                self.reg[b as usize] = self.dt;
+            Instruction::LdVxDt(x) => {
+                self.reg[x as usize] = self.dt;
             }
 
             /*
@@ -432,11 +826,11 @@ impl Vm {
 
                 All execution stops until a key is pressed, then the value of that key is stored in Vx.
             */
-            (0x0f, _, 0x00, 0x0a) => {
+            Instruction::LdVxK(x) => {
                 if let Some(key) = self.kb.iter().position(|&x| x) {
-                    self.reg[b as usize] = key as u8;
+                    self.reg[x as usize] = key as u8;
                 } else {
-                    self.pc -= 2;
+                    self.pc = self.op_addr();
                 }
             }
             /*
@@ -445,8 +839,8 @@ impl Vm {
 
                 DT is set equal to the value of Vx.
             */
-            (0x0f, _, 0x01, 0x05) => {
-                self.dt = self.reg[b as usize];
+            Instruction::LdDtVx(x) => {
+                self.dt = self.reg[x as usize];
             }
 
             /*
@@ -455,8 +849,8 @@ impl Vm {
 
                 ST is set equal to the value of Vx.
             */
-            (0x0f, _, 0x01, 0x08) => {
-                self.st = self.reg[b as usize];
+            Instruction::LdStVx(x) => {
+                self.st = self.reg[x as usize];
             }
 
             /*
@@ -465,8 +859,8 @@ impl Vm {
 
                 The values of I and Vx are added, and the results are stored in I.
             */
-            (0x0f, _, 0x01, 0x0e) => {
-                let reg_val = self.reg[b as usize] as u16;
+            Instruction::AddI(x) => {
+                let reg_val = self.reg[x as usize] as u16;
                 self.ireg = self.ireg.wrapping_add(reg_val)
             }
 
@@ -476,12 +870,22 @@ impl Vm {
 
                 The value of I is set to the location for the hexadecimal sprite corresponding to the value of Vx.
                 See section 2.4, Display, for more information on the Chip-8 hexadecimal font.
+                Only the low nibble of Vx is used, like the COSMAC VIP.
             */
-            (0x0f, _, 0x02, 0x09) => {
-                let num = self.reg[b as usize];
+            Instruction::LdF(x) => {
+                let num = self.reg[x as usize] & 0x0f;
                 self.ireg = num as u16 * 5;
             }
 
+            /*
+                Fx30 - LD HF, Vx (SCHIP)
+                Set I = location of the 10-byte big font sprite for digit Vx.
+            */
+            Instruction::LdHf(x) => {
+                let num = (self.reg[x as usize] & 0x0f) as usize;
+                self.ireg = (BIG_SPRITES_ADDR + num * 10) as u16;
+            }
+
             /*
                 Fx33 - LD B, Vx
                 Store BCD representation of Vx in memory locations I, I+1, and I+2.
@@ -489,16 +893,17 @@ impl Vm {
                 The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I,
                 the tens digit at location I+1, and the ones digit at location I+2.
             */
-            (0x0f, _, 0x03, 0x03) => {
-                let reg_val = self.reg[b as usize];
+            Instruction::LdB(x) => {
+                let reg_val = self.reg[x as usize];
 
                 let d100 = reg_val / 100;
                 let d10 = (reg_val % 100) / 10;
                 let d1 = reg_val % 10;
 
-                self.mem[self.ireg as usize] = d100;
-                self.mem[(self.ireg + 1) as usize] = d10;
-                self.mem[(self.ireg + 2) as usize] = d1;
+                let addr = self.ireg as usize;
+                self.write_mem(addr, d100)?;
+                self.write_mem(addr + 1, d10)?;
+                self.write_mem(addr + 2, d1)?;
             }
 
             /*
@@ -507,9 +912,13 @@ impl Vm {
 
                 The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
             */
-            (0x0f, _, 0x05, 0x05) => {
-                for idx in 0..=(b as usize) {
-                    self.mem[(self.ireg + idx as u16) as usize] = self.reg[idx];
+            Instruction::LdIVx(x) => {
+                for idx in 0..=(x as usize) {
+                    self.write_mem(self.ireg as usize + idx, self.reg[idx])?;
+                }
+
+                if self.quirks.load_store_inc_i {
+                    self.ireg = self.ireg.wrapping_add(x as u16 + 1);
                 }
             }
 
@@ -519,15 +928,87 @@ impl Vm {
 
                 The interpreter reads values from memory starting at location I into registers V0 through Vx.
             */
-            (0x0f, _, 0x06, 0x05) => {
-                for idx in 0..=(b as usize) {
-                    self.reg[idx] = self.mem[(self.ireg + idx as u16) as usize];
+            Instruction::LdVxI(x) => {
+                for idx in 0..=(x as usize) {
+                    self.reg[idx] = self.read_mem(self.ireg as usize + idx)?;
                 }
+
+                if self.quirks.load_store_inc_i {
+                    self.ireg = self.ireg.wrapping_add(x as u16 + 1);
+                }
+            }
+
+            /*
+                Fx75 - LD R, Vx (SCHIP)
+                Store V0 through Vx in the RPL user flags.
+            */
+            Instruction::LdRVx(x) => {
+                let count = x as usize + 1;
+                self.rpl[..count].copy_from_slice(&self.reg[..count]);
+            }
+
+            /*
+                Fx85 - LD Vx, R (SCHIP)
+                Read V0 through Vx from the RPL user flags.
+            */
+            Instruction::LdVxR(x) => {
+                let count = x as usize + 1;
+                self.reg[..count].copy_from_slice(&self.rpl[..count]);
             }
-            _ => {
-                panic!("Unknown opcode: 0x{:x}", opcode)
+
+            Instruction::Unknown(opcode) => {
+                return Err(VmError::UnknownOpcode {
+                    addr: self.op_addr(),
+                    opcode,
+                });
             }
         }
+
+        Ok(())
+    }
+
+    // XO-CHIP: with both planes selected the sprite data for plane 2 follows plane 1
+    fn draw_sprite(&mut self, x: usize, y: usize, n: usize) -> Result<(), VmError> {
+        // Dxy0 draws a 16x16 sprite, two bytes per row
+        let (rows, bytes_per_row) = if n == 0 { (16, 2) } else { (n, 1) };
+        let width = self.vb.width();
+        let height = self.vb.height();
+
+        // the starting position always wraps, only the sprite's pixels are clipped
+        let (x, y) = (x % width, y % height);
+        let clip = self.quirks.clip_sprites;
+
+        let mut flipped = false;
+        let mut addr = self.ireg as usize;
+        for plane in video_buffer::selected(self.planes) {
+            for sprite_y in 0..rows {
+                let mut pixels = 0u16;
+                for _ in 0..bytes_per_row {
+                    pixels = (pixels << 8) | self.read_mem(addr)? as u16;
+                    addr += 1;
+                }
+
+                let sprite_w = bytes_per_row * 8;
+                for sprite_x in 0..sprite_w {
+                    if clip && (x + sprite_x >= width || y + sprite_y >= height) {
+                        continue;
+                    }
+
+                    let px = (x + sprite_x) % width;
+                    let py = (y + sprite_y) % height;
+
+                    let sprite_bit = ((pixels >> (sprite_w - 1 - sprite_x)) & 1) != 0;
+                    let was_on = self.vb.xor_plane_pixel(plane, px, py, sprite_bit);
+                    if was_on && sprite_bit {
+                        flipped = true;
+                    }
+                }
+            }
+        }
+
+        self.reg[0xf] = flipped as u8;
+        self.last_sprite = self.ireg as usize..addr;
+        Ok(())
     }
 
     // once per frame, at rate 60Hz
@@ -544,17 +1025,99 @@ impl Vm {
         }
     }
 
-    fn push(&mut self, val: u16) {
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.reg[y as usize]
+        } else {
+            self.reg[x as usize]
+        }
+    }
+
+    // skips the next instruction, which is 4 bytes long if it is XO-CHIP F000 nnnn
+    fn skip(&mut self) {
+        let pc = self.pc as usize;
+        let long = pc + 1 < MEM_SIZE && self.mem[pc] == 0xf0 && self.mem[pc + 1] == 0x00;
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    // address of the instruction being executed
+    fn op_addr(&self) -> u16 {
+        self.pc.wrapping_sub(2)
+    }
+
+    fn push(&mut self, val: u16) -> Result<(), VmError> {
+        if self.sp as usize >= self.stack.len() {
+            return Err(VmError::StackOverflow {
+                addr: self.op_addr(),
+            });
+        }
+
         self.stack[self.sp as usize] = val;
         self.sp += 1;
+        Ok(())
     }
 
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self) -> Result<u16, VmError> {
+        if self.sp == 0 {
+            return Err(VmError::StackUnderflow {
+                addr: self.op_addr(),
+            });
+        }
+
         self.sp -= 1;
-        self.stack[self.sp as usize]
+        Ok(self.stack[self.sp as usize])
+    }
+
+    fn read_mem(&mut self, addr: usize) -> Result<u8, VmError> {
+        let val = self
+            .mem
+            .get(addr)
+            .copied()
+            .ok_or(VmError::MemoryOutOfBounds {
+                addr: self.op_addr(),
+                target: addr,
+            })?;
+
+        self.report(addr, AccessKind::Read, val);
+        Ok(val)
+    }
+
+    fn write_mem(&mut self, addr: usize, val: u8) -> Result<(), VmError> {
+        let pc = self.op_addr();
+        let cell = self.mem.get_mut(addr).ok_or(VmError::MemoryOutOfBounds {
+            addr: pc,
+            target: addr,
+        })?;
+
+        *cell = val;
+        self.report(addr, AccessKind::Write, val);
+        Ok(())
+    }
+
+    fn report(&mut self, addr: usize, kind: AccessKind, value: u8) {
+        if self.track_accesses {
+            self.accesses.push(MemAccess {
+                addr: addr as u16,
+                kind,
+                value,
+            });
+        }
     }
 
     pub fn set_kb(&mut self, key: usize, state: bool) {
         self.kb[key] = state;
     }
 }
+
+// Vx through Vy inclusive, in reverse when x > y
+fn reg_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+#[cfg(test)]
+mod tests;
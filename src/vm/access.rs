@@ -0,0 +1,13 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+// A memory access made by an instruction's handler, instruction fetches are not reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: u16,
+    pub kind: AccessKind,
+    pub value: u8,
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    UnknownOpcode { addr: u16, opcode: u16 },
+    StackOverflow { addr: u16 },
+    StackUnderflow { addr: u16 },
+    MemoryOutOfBounds { addr: u16, target: usize },
+    PcOutOfBounds { pc: u16 },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::UnknownOpcode { addr, opcode } => {
+                write!(f, "unknown opcode 0x{:04x} at 0x{:03x}", opcode, addr)
+            }
+            VmError::StackOverflow { addr } => write!(f, "stack overflow at 0x{:03x}", addr),
+            VmError::StackUnderflow { addr } => write!(f, "stack underflow at 0x{:03x}", addr),
+            VmError::MemoryOutOfBounds { addr, target } => write!(
+                f,
+                "memory access out of bounds at 0x{:03x} (address 0x{:x})",
+                addr, target
+            ),
+            VmError::PcOutOfBounds { pc } => write!(f, "program counter out of bounds: 0x{:x}", pc),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
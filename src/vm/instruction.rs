@@ -0,0 +1,296 @@
+use std::fmt;
+
+// A decoded opcode. x and y are register indices, kk a byte, n a nibble
+// and addr the 12-bit nnn operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Cls,
+    Ret,
+    Scd(u8),
+    Scu(u8),
+    Scr,
+    Scl,
+    Exit,
+    Low,
+    High,
+    Jp(u16),
+    Call(u16),
+    SeImm { x: u8, kk: u8 },
+    SneImm { x: u8, kk: u8 },
+    SeReg { x: u8, y: u8 },
+    Save { x: u8, y: u8 },
+    Load { x: u8, y: u8 },
+    LdImm { x: u8, kk: u8 },
+    AddImm { x: u8, kk: u8 },
+    LdReg { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    AddReg { x: u8, y: u8 },
+    Sub { x: u8, y: u8 },
+    Shr { x: u8, y: u8 },
+    Subn { x: u8, y: u8 },
+    Shl { x: u8, y: u8 },
+    SneReg { x: u8, y: u8 },
+    LdI(u16),
+    // x is the top nibble of addr, used instead of V0 with the jump quirk
+    JpV0 { x: u8, addr: u16 },
+    Rnd { x: u8, kk: u8 },
+    Drw { x: u8, y: u8, n: u8 },
+    Skp(u8),
+    Sknp(u8),
+    // F000 nnnn, the address is the word following the opcode
+    LdILong,
+    Plane(u8),
+    LdVxDt(u8),
+    LdVxK(u8),
+    LdDtVx(u8),
+    LdStVx(u8),
+    AddI(u8),
+    LdF(u8),
+    LdHf(u8),
+    LdB(u8),
+    LdIVx(u8),
+    LdVxI(u8),
+    LdRVx(u8),
+    LdVxR(u8),
+    Unknown(u16),
+}
+
+impl Instruction {
+    // size in bytes including any operand words
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    // Display with the word after the opcode, which is F000 nnnn's address. Display
+    // alone only has the opcode to go on.
+    pub fn display_with(&self, next: u16) -> String {
+        match self {
+            Instruction::LdILong => format!("LD I, long 0x{:04x}", next),
+            _ => self.to_string(),
+        }
+    }
+
+    // the inverse of decode, operands are masked to their field width
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let xy = |op: u16, x: u8, y: u8, n: u16| {
+            op | ((x as u16 & 0x0f) << 8) | ((y as u16 & 0x0f) << 4) | n
+        };
+        let xkk = |op: u16, x: u8, kk: u8| op | ((x as u16 & 0x0f) << 8) | kk as u16;
+        let fx = |x: u8, kk: u16| 0xf000 | ((x as u16 & 0x0f) << 8) | kk;
+
+        match *self {
+            Cls => 0x00e0,
+            Ret => 0x00ee,
+            Scd(n) => 0x00c0 | (n as u16 & 0x0f),
+            Scu(n) => 0x00d0 | (n as u16 & 0x0f),
+            Scr => 0x00fb,
+            Scl => 0x00fc,
+            Exit => 0x00fd,
+            Low => 0x00fe,
+            High => 0x00ff,
+            Jp(addr) => 0x1000 | (addr & 0x0fff),
+            Call(addr) => 0x2000 | (addr & 0x0fff),
+            SeImm { x, kk } => xkk(0x3000, x, kk),
+            SneImm { x, kk } => xkk(0x4000, x, kk),
+            SeReg { x, y } => xy(0x5000, x, y, 0x0),
+            Save { x, y } => xy(0x5000, x, y, 0x2),
+            Load { x, y } => xy(0x5000, x, y, 0x3),
+            LdImm { x, kk } => xkk(0x6000, x, kk),
+            AddImm { x, kk } => xkk(0x7000, x, kk),
+            LdReg { x, y } => xy(0x8000, x, y, 0x0),
+            Or { x, y } => xy(0x8000, x, y, 0x1),
+            And { x, y } => xy(0x8000, x, y, 0x2),
+            Xor { x, y } => xy(0x8000, x, y, 0x3),
+            AddReg { x, y } => xy(0x8000, x, y, 0x4),
+            Sub { x, y } => xy(0x8000, x, y, 0x5),
+            Shr { x, y } => xy(0x8000, x, y, 0x6),
+            Subn { x, y } => xy(0x8000, x, y, 0x7),
+            Shl { x, y } => xy(0x8000, x, y, 0xe),
+            SneReg { x, y } => xy(0x9000, x, y, 0x0),
+            LdI(addr) => 0xa000 | (addr & 0x0fff),
+            JpV0 { addr, .. } => 0xb000 | (addr & 0x0fff),
+            Rnd { x, kk } => xkk(0xc000, x, kk),
+            Drw { x, y, n } => xy(0xd000, x, y, n as u16 & 0x0f),
+            Skp(x) => xkk(0xe000, x, 0x9e),
+            Sknp(x) => xkk(0xe000, x, 0xa1),
+            LdILong => 0xf000,
+            Plane(n) => fx(n, 0x01),
+            LdVxDt(x) => fx(x, 0x07),
+            LdVxK(x) => fx(x, 0x0a),
+            LdDtVx(x) => fx(x, 0x15),
+            LdStVx(x) => fx(x, 0x18),
+            AddI(x) => fx(x, 0x1e),
+            LdF(x) => fx(x, 0x29),
+            LdHf(x) => fx(x, 0x30),
+            LdB(x) => fx(x, 0x33),
+            LdIVx(x) => fx(x, 0x55),
+            LdVxI(x) => fx(x, 0x65),
+            LdRVx(x) => fx(x, 0x75),
+            LdVxR(x) => fx(x, 0x85),
+            Unknown(opcode) => opcode,
+        }
+    }
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    use Instruction::*;
+
+    let x = ((opcode >> 8) & 0x0f) as u8;
+    let y = ((opcode >> 4) & 0x0f) as u8;
+    let n = (opcode & 0x0f) as u8;
+    let kk = (opcode & 0xff) as u8;
+    let addr = opcode & 0x0fff;
+
+    match (opcode >> 12, x, y, n) {
+        (0x00, 0x00, 0x0e, 0x00) => Cls,
+        (0x00, 0x00, 0x0e, 0x0e) => Ret,
+        (0x00, 0x00, 0x0c, _) => Scd(n),
+        (0x00, 0x00, 0x0d, _) => Scu(n),
+        (0x00, 0x00, 0x0f, 0x0b) => Scr,
+        (0x00, 0x00, 0x0f, 0x0c) => Scl,
+        (0x00, 0x00, 0x0f, 0x0d) => Exit,
+        (0x00, 0x00, 0x0f, 0x0e) => Low,
+        (0x00, 0x00, 0x0f, 0x0f) => High,
+        (0x01, _, _, _) => Jp(addr),
+        (0x02, _, _, _) => Call(addr),
+        (0x03, _, _, _) => SeImm { x, kk },
+        (0x04, _, _, _) => SneImm { x, kk },
+        (0x05, _, _, 0x00) => SeReg { x, y },
+        (0x05, _, _, 0x02) => Save { x, y },
+        (0x05, _, _, 0x03) => Load { x, y },
+        (0x06, _, _, _) => LdImm { x, kk },
+        (0x07, _, _, _) => AddImm { x, kk },
+        (0x08, _, _, 0x00) => LdReg { x, y },
+        (0x08, _, _, 0x01) => Or { x, y },
+        (0x08, _, _, 0x02) => And { x, y },
+        (0x08, _, _, 0x03) => Xor { x, y },
+        (0x08, _, _, 0x04) => AddReg { x, y },
+        (0x08, _, _, 0x05) => Sub { x, y },
+        (0x08, _, _, 0x06) => Shr { x, y },
+        (0x08, _, _, 0x07) => Subn { x, y },
+        (0x08, _, _, 0x0e) => Shl { x, y },
+        (0x09, _, _, 0x00) => SneReg { x, y },
+        (0x0a, _, _, _) => LdI(addr),
+        (0x0b, _, _, _) => JpV0 { x, addr },
+        (0x0c, _, _, _) => Rnd { x, kk },
+        (0x0d, _, _, _) => Drw { x, y, n },
+        (0x0e, _, 0x09, 0x0e) => Skp(x),
+        (0x0e, _, 0x0a, 0x01) => Sknp(x),
+        (0x0f, 0x00, 0x00, 0x00) => LdILong,
+        (0x0f, _, 0x00, 0x01) => Plane(x),
+        (0x0f, _, 0x00, 0x07) => LdVxDt(x),
+        (0x0f, _, 0x00, 0x0a) => LdVxK(x),
+        (0x0f, _, 0x01, 0x05) => LdDtVx(x),
+        (0x0f, _, 0x01, 0x08) => LdStVx(x),
+        (0x0f, _, 0x01, 0x0e) => AddI(x),
+        (0x0f, _, 0x02, 0x09) => LdF(x),
+        (0x0f, _, 0x03, 0x00) => LdHf(x),
+        (0x0f, _, 0x03, 0x03) => LdB(x),
+        (0x0f, _, 0x05, 0x05) => LdIVx(x),
+        (0x0f, _, 0x06, 0x05) => LdVxI(x),
+        (0x0f, _, 0x07, 0x05) => LdRVx(x),
+        (0x0f, _, 0x08, 0x05) => LdVxR(x),
+        _ => Unknown(opcode),
+    }
+}
+
+// Cowgod mnemonics, http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Scd(n) => write!(f, "SCD {}", n),
+            Scu(n) => write!(f, "SCU {}", n),
+            Scr => write!(f, "SCR"),
+            Scl => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            Jp(addr) => write!(f, "JP 0x{:03x}", addr),
+            Call(addr) => write!(f, "CALL 0x{:03x}", addr),
+            SeImm { x, kk } => write!(f, "SE V{:X}, 0x{:02x}", x, kk),
+            SneImm { x, kk } => write!(f, "SNE V{:X}, 0x{:02x}", x, kk),
+            SeReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Save { x, y } => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Load { x, y } => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            LdImm { x, kk } => write!(f, "LD V{:X}, 0x{:02x}", x, kk),
+            AddImm { x, kk } => write!(f, "ADD V{:X}, 0x{:02x}", x, kk),
+            LdReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(addr) => write!(f, "LD I, 0x{:03x}", addr),
+            JpV0 { addr, .. } => write!(f, "JP V0, 0x{:03x}", addr),
+            Rnd { x, kk } => write!(f, "RND V{:X}, 0x{:02x}", x, kk),
+            Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdILong => write!(f, "LD I, long"),
+            Plane(n) => write!(f, "PLANE {}", n),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            LdF(x) => write!(f, "LD F, V{:X}", x),
+            LdHf(x) => write!(f, "LD HF, V{:X}", x),
+            LdB(x) => write!(f, "LD B, V{:X}", x),
+            LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            LdVxR(x) => write!(f, "LD V{:X}, R", x),
+            Unknown(opcode) => write!(f, "db 0x{:02x}, 0x{:02x}", opcode >> 8, opcode & 0xff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_display() {
+        let cases = [
+            (0x00e0, "CLS"),
+            (0x2abc, "CALL 0xabc"),
+            (0x6110, "LD V1, 0x10"),
+            (0x8ab6, "SHR VA, VB"),
+            (0xd015, "DRW V0, V1, 5"),
+            (0xf000, "LD I, long"),
+            (0xf265, "LD V2, [I]"),
+            (0x5121, "db 0x51, 0x21"),
+        ];
+
+        for (opcode, text) in cases {
+            assert_eq!(decode(opcode).to_string(), text);
+        }
+
+        assert_eq!(decode(0xb2f0), Instruction::JpV0 { x: 2, addr: 0x2f0 });
+        assert_eq!(decode(0xf000).size(), 4);
+        assert_eq!(decode(0xf000).display_with(0x1234), "LD I, long 0x1234");
+        assert_eq!(decode(0x00e0).display_with(0x1234), "CLS");
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        for opcode in 0..=u16::MAX {
+            assert_eq!(decode(opcode).encode(), opcode, "0x{:04x}", opcode);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+// Behaviour that differs between CHIP-8 interpreters.
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+// All quirks off is how this interpreter always behaved, and stays the default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    // 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65 leave I pointing past the last register
+    pub load_store_inc_i: bool,
+    // Bnnn jumps to nnn + Vx (x is the high nibble of nnn) instead of nnn + V0
+    pub jump_with_vx: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    // Dxyn clips sprites at the screen edge instead of wrapping them around
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    pub fn preset(&self) -> Option<QuirksPreset> {
+        QuirksPreset::ALL
+            .into_iter()
+            .find(|preset| preset.quirks() == *self)
+    }
+}
+
+// the names are the same for --quirks and the ROM database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuirksPreset {
+    CosmacVip,
+    Chip48,
+    Schip,
+    XoChip,
+}
+
+impl QuirksPreset {
+    pub const ALL: [QuirksPreset; 4] = [
+        QuirksPreset::CosmacVip,
+        QuirksPreset::Chip48,
+        QuirksPreset::Schip,
+        QuirksPreset::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirksPreset::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_inc_i: true,
+                jump_with_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+            },
+            QuirksPreset::Chip48 => Quirks {
+                shift_uses_vy: false,
+                load_store_inc_i: true,
+                jump_with_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+            },
+            QuirksPreset::Schip => Quirks {
+                shift_uses_vy: false,
+                load_store_inc_i: false,
+                jump_with_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+            },
+            QuirksPreset::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_inc_i: true,
+                jump_with_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for QuirksPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QuirksPreset::CosmacVip => "COSMAC VIP",
+            QuirksPreset::Chip48 => "CHIP-48",
+            QuirksPreset::Schip => "SCHIP",
+            QuirksPreset::XoChip => "XO-CHIP",
+        };
+
+        f.write_str(name)
+    }
+}
@@ -0,0 +1,667 @@
+use super::*;
+
+// A Vm with `regs` set and nothing but zeros at 0x200
+fn vm_with(regs: &[(usize, u8)]) -> Vm {
+    let mut vm = Vm::from_bytes(&[]);
+    for &(idx, val) in regs {
+        vm.set_reg(idx, val);
+    }
+    vm
+}
+
+// writes `opcode` at pc and executes it
+fn exec(vm: &mut Vm, opcode: u16) -> Result<(), VmError> {
+    let pc = vm.pc() as usize;
+    let [hi, lo] = opcode.to_be_bytes();
+    vm.set_mem(pc, hi);
+    vm.set_mem(pc + 1, lo);
+    vm.tick()
+}
+
+fn run(vm: &mut Vm, opcode: u16) {
+    exec(vm, opcode).unwrap_or_else(|err| panic!("{:04x}: {}", opcode, err));
+}
+
+fn pixels(vm: &Vm, y: usize, xs: std::ops::Range<usize>) -> Vec<bool> {
+    xs.map(|x| vm.vb.get_pixel(x, y)).collect()
+}
+
+#[test]
+fn test_from_bytes() {
+    let vm = Vm::from_bytes(&[0x12, 0x34]);
+    assert_eq!(&vm.mem()[0x200..0x202], [0x12, 0x34]);
+    assert_eq!(vm.pc(), 0x200);
+    assert_eq!(vm.rom_range(), 0x200..0x202);
+    assert_eq!(vm.rom_path(), None);
+    // the font is there
+    assert_eq!(vm.mem()[..5], [0xf0, 0x90, 0x90, 0x90, 0xf0]);
+}
+
+#[test]
+fn test_cls() {
+    let mut vm = vm_with(&[]);
+    vm.vb.set_pixel(3, 4, true);
+    run(&mut vm, 0x00e0);
+    assert!(!vm.vb.get_pixel(3, 4));
+    assert_eq!(vm.pc(), 0x202);
+}
+
+#[test]
+fn test_jp_call_ret() {
+    let mut vm = vm_with(&[]);
+    run(&mut vm, 0x1234);
+    assert_eq!(vm.pc(), 0x234);
+
+    run(&mut vm, 0x2300);
+    assert_eq!(vm.pc(), 0x300);
+    assert_eq!(vm.sp(), 1);
+    assert_eq!(vm.stack()[0], 0x236);
+
+    run(&mut vm, 0x00ee);
+    assert_eq!(vm.pc(), 0x236);
+    assert_eq!(vm.sp(), 0);
+
+    assert_eq!(
+        exec(&mut vm, 0x00ee),
+        Err(VmError::StackUnderflow { addr: 0x236 })
+    );
+}
+
+#[test]
+fn test_stack_overflow() {
+    // calls itself until the stack is full
+    let mut vm = Vm::from_bytes(&[0x22, 0x00]);
+    for _ in 0..16 {
+        vm.tick().unwrap();
+    }
+    assert_eq!(vm.sp(), 16);
+    assert_eq!(vm.tick(), Err(VmError::StackOverflow { addr: 0x200 }));
+}
+
+#[test]
+fn test_skip_imm() {
+    let mut vm = vm_with(&[(1, 0x42)]);
+    run(&mut vm, 0x3142);
+    assert_eq!(vm.pc(), 0x204);
+    run(&mut vm, 0x3143);
+    assert_eq!(vm.pc(), 0x206);
+
+    run(&mut vm, 0x4142);
+    assert_eq!(vm.pc(), 0x208);
+    run(&mut vm, 0x4143);
+    assert_eq!(vm.pc(), 0x20c);
+}
+
+#[test]
+fn test_skip_reg() {
+    let mut vm = vm_with(&[(1, 7), (2, 7), (3, 8)]);
+    run(&mut vm, 0x5120);
+    assert_eq!(vm.pc(), 0x204);
+    run(&mut vm, 0x5130);
+    assert_eq!(vm.pc(), 0x206);
+
+    run(&mut vm, 0x9130);
+    assert_eq!(vm.pc(), 0x20a);
+    run(&mut vm, 0x9120);
+    assert_eq!(vm.pc(), 0x20c);
+}
+
+#[test]
+fn test_skip_long() {
+    // a skip steps over all of F000 nnnn
+    let mut vm = Vm::from_bytes(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34]);
+    vm.tick().unwrap();
+    assert_eq!(vm.pc(), 0x206);
+}
+
+#[test]
+fn test_ld_add_imm() {
+    let mut vm = vm_with(&[]);
+    run(&mut vm, 0x6aff);
+    assert_eq!(vm.reg()[0xa], 0xff);
+
+    // wraps and leaves VF alone
+    run(&mut vm, 0x7a02);
+    assert_eq!(vm.reg()[0xa], 0x01);
+    assert_eq!(vm.reg()[0xf], 0);
+}
+
+#[test]
+fn test_logic() {
+    let mut vm = vm_with(&[(0, 0x5a), (1, 0x3c), (0xf, 9)]);
+    run(&mut vm, 0x8200);
+    assert_eq!(vm.reg()[2], 0x5a);
+
+    run(&mut vm, 0x8211);
+    assert_eq!(vm.reg()[2], 0x7e);
+    run(&mut vm, 0x8012);
+    assert_eq!(vm.reg()[0], 0x18);
+    run(&mut vm, 0x8213);
+    assert_eq!(vm.reg()[2], 0x42);
+    assert_eq!(vm.reg()[0xf], 9);
+}
+
+#[test]
+fn test_logic_vf_reset() {
+    for opcode in [0x8011, 0x8012, 0x8013] {
+        let mut vm = vm_with(&[(0xf, 1)]);
+        vm.set_quirks(Quirks {
+            vf_reset: true,
+            ..Quirks::default()
+        });
+        run(&mut vm, opcode);
+        assert_eq!(vm.reg()[0xf], 0, "{:04x}", opcode);
+    }
+}
+
+#[test]
+fn test_add_carry() {
+    let mut vm = vm_with(&[(0, 0xf0), (1, 0x0f), (2, 0x01)]);
+    run(&mut vm, 0x8014);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0xff, 0));
+    run(&mut vm, 0x8024);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x00, 1));
+
+    // with VF as Vx the flag wins over the sum
+    let mut vm = vm_with(&[(0xf, 0xff), (1, 0x01)]);
+    run(&mut vm, 0x8f14);
+    assert_eq!(vm.reg()[0xf], 1);
+
+    let mut vm = vm_with(&[(0xf, 0x10), (1, 0x10)]);
+    run(&mut vm, 0x8f14);
+    assert_eq!(vm.reg()[0xf], 0);
+
+    // and with VF as Vy the old VF is added
+    let mut vm = vm_with(&[(0, 0xff), (0xf, 0x01)]);
+    run(&mut vm, 0x80f4);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x00, 1));
+}
+
+#[test]
+fn test_sub_borrow() {
+    // VF is 1 when there is no borrow, including Vx == Vy
+    for (a, b, res, flag) in [(5, 3, 2, 1), (3, 5, 0xfe, 0), (4, 4, 0, 1)] {
+        let mut vm = vm_with(&[(0, a), (1, b)]);
+        run(&mut vm, 0x8015);
+        assert_eq!((vm.reg()[0], vm.reg()[0xf]), (res, flag), "{} - {}", a, b);
+
+        let mut vm = vm_with(&[(0, b), (1, a)]);
+        run(&mut vm, 0x8017);
+        assert_eq!((vm.reg()[0], vm.reg()[0xf]), (res, flag), "{} =- {}", a, b);
+    }
+
+    let mut vm = vm_with(&[(0xf, 0x10), (1, 0x20)]);
+    run(&mut vm, 0x8f15);
+    assert_eq!(vm.reg()[0xf], 0);
+
+    let mut vm = vm_with(&[(0xf, 0x10), (1, 0x20)]);
+    run(&mut vm, 0x8f17);
+    assert_eq!(vm.reg()[0xf], 1);
+}
+
+#[test]
+fn test_shifts() {
+    let mut vm = vm_with(&[(0, 0x81)]);
+    run(&mut vm, 0x8006);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x40, 1));
+    run(&mut vm, 0x8006);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x20, 0));
+
+    let mut vm = vm_with(&[(0, 0x81)]);
+    run(&mut vm, 0x800e);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x02, 1));
+    run(&mut vm, 0x800e);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x04, 0));
+
+    // the flag is written last
+    let mut vm = vm_with(&[(0xf, 0x02)]);
+    run(&mut vm, 0x8f06);
+    assert_eq!(vm.reg()[0xf], 0);
+    let mut vm = vm_with(&[(0xf, 0x80)]);
+    run(&mut vm, 0x8f0e);
+    assert_eq!(vm.reg()[0xf], 1);
+}
+
+#[test]
+fn test_shifts_vy() {
+    let mut vm = vm_with(&[(0, 0x01), (1, 0x80)]);
+    vm.set_quirks(QuirksPreset::CosmacVip.quirks());
+    run(&mut vm, 0x8016);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x40, 0));
+    assert_eq!(vm.reg()[1], 0x80);
+
+    let mut vm = vm_with(&[(0, 0x80), (1, 0x41)]);
+    vm.set_quirks(QuirksPreset::CosmacVip.quirks());
+    run(&mut vm, 0x801e);
+    assert_eq!((vm.reg()[0], vm.reg()[0xf]), (0x82, 0));
+}
+
+#[test]
+fn test_ld_i_jp_v0() {
+    let mut vm = vm_with(&[(0, 0x10), (3, 0x20)]);
+    run(&mut vm, 0xa123);
+    assert_eq!(vm.ireg(), 0x123);
+
+    run(&mut vm, 0xb300);
+    assert_eq!(vm.pc(), 0x310);
+
+    let mut vm = vm_with(&[(0, 0x10), (3, 0x20)]);
+    vm.set_quirks(Quirks {
+        jump_with_vx: true,
+        ..Quirks::default()
+    });
+    run(&mut vm, 0xb300);
+    assert_eq!(vm.pc(), 0x320);
+}
+
+#[test]
+fn test_rnd() {
+    let mut vm = vm_with(&[(0, 0xff)]);
+    run(&mut vm, 0xc000);
+    assert_eq!(vm.reg()[0], 0);
+
+    for _ in 0..64 {
+        run(&mut vm, 0xc00f);
+        assert_eq!(vm.reg()[0] & 0xf0, 0);
+    }
+}
+
+#[test]
+fn test_rnd_seed() {
+    let rolls = |vm: &mut Vm| -> Vec<u8> {
+        (0..16)
+            .map(|_| {
+                run(vm, 0xc0ff);
+                vm.reg()[0]
+            })
+            .collect()
+    };
+
+    let mut a = vm_with(&[]);
+    let mut b = vm_with(&[]);
+    a.set_seed(42);
+    b.set_seed(42);
+    let first = rolls(&mut a);
+    assert_eq!(first, rolls(&mut b));
+    assert_ne!(first, rolls(&mut a));
+
+    // a reset starts over from the seed
+    a.reset();
+    assert_eq!(rolls(&mut a), first);
+
+    b.set_seed(43);
+    assert_ne!(rolls(&mut b), first);
+}
+
+#[test]
+fn test_rnd_state() {
+    let mut vm = vm_with(&[]);
+    vm.set_seed(7);
+    run(&mut vm, 0xc0ff);
+
+    let state = vm.save_state();
+    run(&mut vm, 0xc0ff);
+    let next = vm.reg()[0];
+
+    // a VM with another seed continues where the saved one was
+    let mut other = vm_with(&[]);
+    other.set_seed(8);
+    other.load_state(&state).unwrap();
+    run(&mut other, 0xc0ff);
+    assert_eq!(other.reg()[0], next);
+}
+
+#[test]
+fn test_state_roundtrip() {
+    let mut vm = vm_with(&[(0, 3), (1, 4), (5, 0x42)]);
+    vm.set_quirks(QuirksPreset::XoChip.quirks());
+    run(&mut vm, 0x00ff);
+    run(&mut vm, 0xf029);
+    run(&mut vm, 0xd015);
+    run(&mut vm, 0xf515);
+    run(&mut vm, 0x2300);
+    vm.set_kb(0xa, true);
+
+    let state = vm.save_state();
+    let mut other = Vm::from_bytes(&[]);
+    other.load_state(&state).unwrap();
+
+    assert_eq!(other.pc(), 0x300);
+    assert_eq!(
+        other.stack()[..other.sp() as usize],
+        vm.stack()[..vm.sp() as usize]
+    );
+    assert_eq!(other.reg(), vm.reg());
+    assert_eq!(other.ireg(), vm.ireg());
+    assert_eq!(other.dt(), 0x42);
+    assert_eq!(other.kb, vm.kb);
+    assert_eq!(
+        other.vb.words().collect::<Vec<_>>(),
+        vm.vb.words().collect::<Vec<_>>()
+    );
+    assert_eq!(other.save_state(), state);
+
+    // little endian whatever the host is, starting with the hires size
+    let words = &state[state.len() - std::mem::size_of::<VideoBuffer>()..];
+    assert_eq!(words[..8], [128, 0, 0, 0, 64, 0, 0, 0]);
+
+    let mut newer = state.clone();
+    newer[4] = 2;
+    assert_eq!(
+        other.load_state(&newer),
+        Err(StateError::UnsupportedVersion(2))
+    );
+}
+
+#[test]
+fn test_drw_collision() {
+    // the font's 0 at (1, 2)
+    let mut vm = vm_with(&[(0, 1), (1, 2)]);
+    run(&mut vm, 0xd015);
+    assert_eq!(vm.reg()[0xf], 0);
+    assert_eq!(pixels(&vm, 2, 0..6), [false, true, true, true, true, false]);
+    assert_eq!(
+        pixels(&vm, 3, 0..6),
+        [false, true, false, false, true, false]
+    );
+    assert_eq!(vm.last_sprite(), 0..5);
+
+    // drawing it again erases it
+    run(&mut vm, 0xd015);
+    assert_eq!(vm.reg()[0xf], 1);
+    assert!((0..5).all(|y| pixels(&vm, y, 0..8).iter().all(|&on| !on)));
+
+    // a row of 0s doesn't collide with anything
+    vm.vb.set_pixel(1, 2, true);
+    vm.set_ireg(0x300);
+    run(&mut vm, 0xd011);
+    assert_eq!(vm.reg()[0xf], 0);
+    assert!(vm.vb.get_pixel(1, 2));
+}
+
+#[test]
+fn test_drw_wrap() {
+    let mut vm = vm_with(&[(0, 62), (1, 31)]);
+    vm.set_mem(0x300, 0xff);
+    vm.set_mem(0x301, 0x81);
+    vm.set_ireg(0x300);
+    run(&mut vm, 0xd012);
+
+    assert_eq!(pixels(&vm, 31, 60..64), [false, false, true, true]);
+    assert_eq!(
+        pixels(&vm, 31, 0..7),
+        [true, true, true, true, true, true, false]
+    );
+    // the second row wraps to the top
+    assert_eq!(pixels(&vm, 0, 60..64), [false, false, true, false]);
+    assert_eq!(
+        pixels(&vm, 0, 0..6),
+        [false, false, false, false, false, true]
+    );
+
+    // the starting position wraps too
+    let mut vm = vm_with(&[(0, 64 + 3), (1, 32 + 1)]);
+    run(&mut vm, 0xd011);
+    assert!(vm.vb.get_pixel(3, 1));
+}
+
+#[test]
+fn test_drw_clip() {
+    let mut vm = vm_with(&[(0, 62), (1, 31)]);
+    vm.set_quirks(Quirks {
+        clip_sprites: true,
+        ..Quirks::default()
+    });
+    vm.set_mem(0x300, 0xff);
+    vm.set_mem(0x301, 0xff);
+    vm.set_ireg(0x300);
+    run(&mut vm, 0xd012);
+
+    assert_eq!(pixels(&vm, 31, 60..64), [false, false, true, true]);
+    assert!(pixels(&vm, 31, 0..8).iter().all(|&on| !on));
+    assert!(pixels(&vm, 0, 0..64).iter().all(|&on| !on));
+
+    // only the pixels are clipped, not the starting position
+    let mut vm = vm_with(&[(0, 64 + 3), (1, 1)]);
+    vm.set_quirks(QuirksPreset::CosmacVip.quirks());
+    run(&mut vm, 0xd011);
+    assert!(vm.vb.get_pixel(3, 1));
+}
+
+#[test]
+fn test_drw_16x16() {
+    let mut vm = vm_with(&[]);
+    run(&mut vm, 0x00ff);
+    assert!(vm.vb.is_hires());
+
+    for addr in 0x300..0x320 {
+        vm.set_mem(addr, 0xff);
+    }
+    vm.set_ireg(0x300);
+    run(&mut vm, 0xd000);
+    assert!(vm.vb.get_pixel(15, 15));
+    assert!(!vm.vb.get_pixel(16, 15));
+    assert!(!vm.vb.get_pixel(15, 16));
+    assert_eq!(vm.last_sprite(), 0x300..0x320);
+
+    run(&mut vm, 0x00fe);
+    assert!(!vm.vb.is_hires());
+}
+
+#[test]
+fn test_keys() {
+    let mut vm = vm_with(&[(0, 0x05), (1, 0x15)]);
+    run(&mut vm, 0xe09e);
+    assert_eq!(vm.pc(), 0x202);
+    run(&mut vm, 0xe0a1);
+    assert_eq!(vm.pc(), 0x206);
+
+    vm.set_kb(5, true);
+    run(&mut vm, 0xe09e);
+    assert_eq!(vm.pc(), 0x20a);
+    run(&mut vm, 0xe0a1);
+    assert_eq!(vm.pc(), 0x20c);
+
+    // only the low nibble of Vx picks the key
+    run(&mut vm, 0xe19e);
+    assert_eq!(vm.pc(), 0x210);
+}
+
+#[test]
+fn test_wait_key() {
+    let mut vm = vm_with(&[]);
+    for _ in 0..3 {
+        run(&mut vm, 0xf30a);
+        assert_eq!(vm.pc(), 0x200);
+    }
+
+    vm.set_kb(0xb, true);
+    run(&mut vm, 0xf30a);
+    assert_eq!(vm.pc(), 0x202);
+    assert_eq!(vm.reg()[3], 0xb);
+}
+
+#[test]
+fn test_timers() {
+    let mut vm = vm_with(&[(0, 2), (1, 9)]);
+    run(&mut vm, 0xf015);
+    run(&mut vm, 0xf118);
+    assert_eq!((vm.dt(), vm.st()), (2, 9));
+
+    vm.delay_timer();
+    vm.sound_timer();
+    run(&mut vm, 0xf207);
+    assert_eq!(vm.reg()[2], 1);
+    assert_eq!(vm.st(), 8);
+
+    // they stop at 0
+    vm.delay_timer();
+    vm.delay_timer();
+    run(&mut vm, 0xf207);
+    assert_eq!(vm.reg()[2], 0);
+}
+
+#[test]
+fn test_add_i() {
+    let mut vm = vm_with(&[(0, 0x10)]);
+    vm.set_ireg(0xff8);
+    run(&mut vm, 0xf01e);
+    assert_eq!(vm.ireg(), 0x1008);
+    assert_eq!(vm.reg()[0xf], 0);
+
+    // I is 16 bits wide for XO-CHIP and wraps at the top
+    vm.set_ireg(0xfffe);
+    run(&mut vm, 0xf01e);
+    assert_eq!(vm.ireg(), 0x000e);
+}
+
+#[test]
+fn test_font() {
+    let mut vm = vm_with(&[(0, 0xa), (1, 0x1a)]);
+    run(&mut vm, 0xf029);
+    assert_eq!(vm.ireg(), 50);
+    run(&mut vm, 0xf129);
+    assert_eq!(vm.ireg(), 50);
+
+    run(&mut vm, 0xf030);
+    assert_eq!(vm.ireg() as usize, BIG_SPRITES_ADDR + 100);
+    assert_eq!(
+        &vm.mem()[vm.ireg() as usize..][..10],
+        &BIG_SPRITES[100..110]
+    );
+}
+
+#[test]
+fn test_bcd() {
+    for (val, digits) in [(0, [0, 0, 0]), (9, [0, 0, 9]), (255, [2, 5, 5])] {
+        let mut vm = vm_with(&[(4, val)]);
+        vm.set_ireg(0x300);
+        run(&mut vm, 0xf433);
+        assert_eq!(vm.mem()[0x300..0x303], digits, "{}", val);
+        assert_eq!(vm.ireg(), 0x300);
+    }
+}
+
+#[test]
+fn test_load_store() {
+    let mut vm = vm_with(&[(0, 1), (1, 2), (2, 3), (3, 4)]);
+    vm.set_ireg(0x300);
+    run(&mut vm, 0xf255);
+    assert_eq!(vm.mem()[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(vm.ireg(), 0x300);
+
+    for idx in 0..4 {
+        vm.set_reg(idx, 0);
+    }
+    run(&mut vm, 0xf165);
+    assert_eq!(vm.reg()[..4], [1, 2, 0, 0]);
+    assert_eq!(vm.ireg(), 0x300);
+
+    vm.set_quirks(Quirks {
+        load_store_inc_i: true,
+        ..Quirks::default()
+    });
+    run(&mut vm, 0xf155);
+    assert_eq!(vm.ireg(), 0x302);
+    run(&mut vm, 0xf065);
+    assert_eq!(vm.ireg(), 0x303);
+}
+
+#[test]
+fn test_load_store_bounds() {
+    let mut vm = vm_with(&[]);
+    vm.set_ireg(0xffff);
+    run(&mut vm, 0xf055);
+    assert_eq!(
+        exec(&mut vm, 0xf155),
+        Err(VmError::MemoryOutOfBounds {
+            addr: 0x202,
+            target: 0x10000
+        })
+    );
+}
+
+#[test]
+fn test_save_load_range() {
+    let mut vm = vm_with(&[(2, 0xa), (3, 0xb), (4, 0xc)]);
+    vm.set_ireg(0x300);
+    run(&mut vm, 0x5242);
+    assert_eq!(vm.mem()[0x300..0x303], [0xa, 0xb, 0xc]);
+
+    // reversed
+    run(&mut vm, 0x5422);
+    assert_eq!(vm.mem()[0x300..0x303], [0xc, 0xb, 0xa]);
+    assert_eq!(vm.ireg(), 0x300);
+
+    run(&mut vm, 0x5793);
+    assert_eq!(vm.reg()[7..10], [0xc, 0xb, 0xa]);
+}
+
+#[test]
+fn test_ld_i_long_plane() {
+    let mut vm = Vm::from_bytes(&[0xf0, 0x00, 0xbe, 0xef, 0xf2, 0x01]);
+    vm.tick().unwrap();
+    assert_eq!(vm.ireg(), 0xbeef);
+    assert_eq!(vm.pc(), 0x204);
+
+    // drawing on plane 2 only
+    vm.tick().unwrap();
+    vm.set_ireg(0);
+    run(&mut vm, 0xd001);
+    assert!(vm.vb.get_plane_pixel(1, 0, 0));
+    assert!(!vm.vb.get_plane_pixel(0, 0, 0));
+}
+
+#[test]
+fn test_rpl() {
+    let mut vm = vm_with(&[(0, 7), (1, 8), (2, 9)]);
+    run(&mut vm, 0xf175);
+    assert_eq!(vm.rpl()[..3], [7, 8, 0]);
+
+    vm.set_reg(0, 0);
+    vm.set_reg(1, 0);
+    run(&mut vm, 0xf185);
+    assert_eq!(vm.reg()[..3], [7, 8, 9]);
+}
+
+#[test]
+fn test_scroll() {
+    let mut vm = vm_with(&[]);
+    vm.vb.set_pixel(10, 10, true);
+    run(&mut vm, 0x00c2);
+    assert!(vm.vb.get_pixel(10, 12));
+    run(&mut vm, 0x00d1);
+    assert!(vm.vb.get_pixel(10, 11));
+    run(&mut vm, 0x00fb);
+    assert!(vm.vb.get_pixel(14, 11));
+    run(&mut vm, 0x00fc);
+    assert!(vm.vb.get_pixel(10, 11));
+}
+
+#[test]
+fn test_exit() {
+    let mut vm = vm_with(&[]);
+    run(&mut vm, 0x00fd);
+    assert!(vm.exited());
+
+    // nothing runs after that
+    let pc = vm.pc();
+    vm.tick().unwrap();
+    assert_eq!(vm.pc(), pc);
+}
+
+#[test]
+fn test_errors() {
+    let mut vm = vm_with(&[]);
+    assert_eq!(
+        exec(&mut vm, 0x5121),
+        Err(VmError::UnknownOpcode {
+            addr: 0x200,
+            opcode: 0x5121
+        })
+    );
+
+    let mut vm = vm_with(&[]);
+    vm.set_pc(0xffff);
+    assert_eq!(vm.tick(), Err(VmError::PcOutOfBounds { pc: 0xffff }));
+}
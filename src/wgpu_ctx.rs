@@ -5,10 +5,12 @@ use std::{borrow::Cow, num::NonZeroU64};
 use wgpu::util::DeviceExt;
 use winit::window::Window;
 
+use crate::debugger::Debugger;
 use crate::egui::EguiRenderer;
 use crate::settings::Settings;
 use crate::ui::Ui;
 use crate::video_buffer::VideoBuffer;
+use crate::vm::{Vm, VmError};
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -45,8 +47,7 @@ pub struct WgpuCtx<'window> {
     pub egui_renderer: EguiRenderer,
     ui: Ui,
 
-    fg_color_uniform_buffer: wgpu::Buffer,
-    bg_color_uniform_buffer: wgpu::Buffer,
+    palette_uniform_buffer: wgpu::Buffer,
     screen_size_uniform_buffer: wgpu::Buffer,
     time_uniform_buffer: wgpu::Buffer,
     pp_enabled_uniform_buffer: wgpu::Buffer,
@@ -161,25 +162,13 @@ impl<'window> WgpuCtx<'window> {
             ..Default::default()
         });
 
-        let (fg_color, bg_color) = {
-            let settings = settings.read().unwrap();
+        let palette = { settings.read().unwrap().palette() };
 
-            (settings.fg_color, settings.bg_color)
-        };
-
-        let fg_color_uniform_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("color uniform buffer"),
-                contents: bytemuck::bytes_of(&fg_color),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-
-        let bg_color_uniform_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("background color uniform buffer"),
-                contents: bytemuck::bytes_of(&bg_color),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        let palette_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("palette uniform buffer"),
+            contents: bytemuck::bytes_of(&palette),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
         let screen_size_uniform_buffer =
             device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -234,16 +223,6 @@ impl<'window> WgpuCtx<'window> {
                         },
                         count: None,
                     },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
                 ],
             });
 
@@ -253,14 +232,10 @@ impl<'window> WgpuCtx<'window> {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: fg_color_uniform_buffer.as_entire_binding(),
+                    resource: palette_uniform_buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: bg_color_uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
                     resource: screen_size_uniform_buffer.as_entire_binding(),
                 },
             ],
@@ -491,8 +466,7 @@ impl<'window> WgpuCtx<'window> {
             egui_renderer,
             ui,
 
-            fg_color_uniform_buffer,
-            bg_color_uniform_buffer,
+            palette_uniform_buffer,
             screen_size_uniform_buffer,
             time_uniform_buffer,
             pp_enabled_uniform_buffer,
@@ -617,22 +591,18 @@ impl<'window> WgpuCtx<'window> {
     fn update_buffers(&self, vb: &VideoBuffer) {
         use bytemuck::bytes_of;
 
-        let (fg_color, bg_color, pp_enabled, sepia_amount) = {
+        let (palette, pp_enabled, sepia_amount) = {
             let settings = self.settings.read().unwrap();
 
             (
-                settings.fg_color,
-                settings.bg_color,
+                settings.palette(),
                 settings.pp_enabled as u32,
                 settings.sepia_amount,
             )
         };
 
         self.queue
-            .write_buffer(&self.fg_color_uniform_buffer, 0, bytes_of(&fg_color));
-
-        self.queue
-            .write_buffer(&self.bg_color_uniform_buffer, 0, bytes_of(&bg_color));
+            .write_buffer(&self.palette_uniform_buffer, 0, bytes_of(&palette));
 
         self.queue
             .write_buffer(&self.video_buffer, 0, vb.as_bytes());
@@ -651,8 +621,13 @@ impl<'window> WgpuCtx<'window> {
         );
     }
 
-    pub fn draw(&mut self, vb: &VideoBuffer) {
-        self.update_buffers(vb);
+    // whatever the settings window hasn't written yet
+    pub fn save_settings(&mut self) {
+        self.ui.save_settings();
+    }
+
+    pub fn draw(&mut self, vm: &mut Vm, debugger: &mut Debugger, halted: Option<&VmError>) {
+        self.update_buffers(&vm.vb);
 
         let target_texture = self.surface.get_current_texture().unwrap();
         let target_view = target_texture
@@ -722,13 +697,17 @@ impl<'window> WgpuCtx<'window> {
             pp_pass.draw(0..6, 0..1);
         }
 
-        let show_settings = {
+        let (show_settings, show_debugger) = {
             let settings = self.settings.read().unwrap();
-            settings.show_settings
+            (settings.show_settings, settings.show_debugger)
         };
+        if !show_settings {
+            self.ui.save_settings();
+        }
 
-        if show_settings {
-            self.render_egui(&mut encoder, &target_view);
+        if show_settings || show_debugger || halted.is_some() {
+            let debugger = show_debugger.then_some((vm, debugger));
+            self.render_egui(&mut encoder, &target_view, show_settings, debugger, halted);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -740,10 +719,23 @@ impl<'window> WgpuCtx<'window> {
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
         texture_view: &wgpu::TextureView,
+        show_settings: bool,
+        debugger: Option<(&mut Vm, &mut Debugger)>,
+        halted: Option<&VmError>,
     ) {
         self.egui_renderer.begin_frame(self.window.as_ref());
 
-        self.ui.draw(&self.egui_renderer);
+        if let Some((vm, debugger)) = debugger {
+            self.ui.draw_debugger(&self.egui_renderer, vm, debugger);
+        }
+
+        if show_settings {
+            self.ui.draw(&self.egui_renderer);
+        }
+
+        if let Some(err) = halted {
+            self.ui.draw_halted(&self.egui_renderer, err);
+        }
 
         self.egui_renderer.end_frame_and_draw(
             self.window.as_ref(),
@@ -0,0 +1,208 @@
+// Runs the ROMs in tests/roms headlessly under every quirk preset and compares
+// the screen and registers at the end with tests/golden/<rom>.<preset>.txt.
+//
+// Only the Timendus cases at the bottom are conformance tests, and they stay
+// ignored until the suite is vendored. The .asm ROMs were written for this
+// repo, their goldens were written by this emulator, so they catch changes in
+// behaviour but don't show that the behaviour is right.
+//
+// `UPDATE_GOLDEN=1 cargo test --test conformance` writes the goldens instead,
+// look at the diff before committing them. tests/roms/README.md has the
+// details on the ROMs themselves.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::headless::{self, Runner};
+use chip8::vm::{QuirksPreset, Vm};
+use chip8::{asm, octo};
+use clap::ValueEnum;
+
+// fixed so the goldens don't move with the default speed
+const TICKS_PER_FRAME: u32 = 15;
+
+struct Case {
+    rom: &'static str,
+    frames: u64,
+    // pokes before the first instruction, the Timendus ROMs read 0x1ff to
+    // skip their menu
+    setup: &'static [(usize, u8)],
+    // a script for headless::parse_script
+    input: &'static str,
+}
+
+impl Case {
+    fn new(rom: &'static str, frames: u64) -> Self {
+        Self {
+            rom,
+            frames,
+            setup: &[],
+            input: "",
+        }
+    }
+}
+
+fn rom_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms")
+}
+
+fn load(path: &Path) -> Vec<u8> {
+    let result = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("asm") => asm::assemble_file(path)
+            .map(|program| program.rom)
+            .map_err(|err| err.to_string()),
+        Some("8o") => octo::compile_file(path)
+            .map(|program| program.rom)
+            .map_err(|err| err.to_string()),
+        _ => fs::read(path).map_err(|err| format!("{}: {}", path.display(), err)),
+    };
+
+    result.unwrap_or_else(|err| panic!("{}", err))
+}
+
+fn run(case: &Case, preset: QuirksPreset) -> String {
+    let path = rom_dir().join(case.rom);
+    let rom = load(&path);
+
+    let mut vm = Vm::new();
+    vm.load_rom_bytes(path.display().to_string(), &rom)
+        .unwrap_or_else(|err| panic!("{}: {}", case.rom, err));
+    vm.set_quirks(preset.quirks());
+    for &(addr, val) in case.setup {
+        vm.set_mem(addr, val);
+    }
+
+    let events = headless::parse_script(case.input)
+        .unwrap_or_else(|err| panic!("{}: input script {}", case.rom, err));
+    let mut runner = Runner::new(vm, TICKS_PER_FRAME, events);
+    runner
+        .run_frames(case.frames)
+        .unwrap_or_else(|err| panic!("{} ({}): {}", case.rom, preset, err));
+
+    format!(
+        "{}\n{}",
+        headless::screen_text(&runner.vm.vb),
+        headless::registers_json(&runner)
+    )
+}
+
+fn check(case: Case) {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+
+    for preset in QuirksPreset::ALL {
+        let actual = run(&case, preset);
+
+        let slug = preset.to_possible_value().unwrap();
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.{}.txt", case.rom, slug.get_name()));
+
+        if update {
+            fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{} ({}) differs from {}\n--- expected\n{}\n--- got\n{}",
+                case.rom,
+                preset,
+                golden.display(),
+                expected,
+                actual
+            )),
+            Err(err) => failures.push(format!(
+                "{}: {}, run with UPDATE_GOLDEN=1 to create it",
+                golden.display(),
+                err
+            )),
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+// Regression ROMs, written here
+
+#[test]
+fn logo() {
+    check(Case::new("logo.asm", 20));
+}
+
+#[test]
+fn opcodes() {
+    check(Case::new("opcodes.asm", 30));
+}
+
+#[test]
+fn flags() {
+    check(Case::new("flags.asm", 20));
+}
+
+#[test]
+fn quirks() {
+    check(Case::new("quirks.asm", 20));
+}
+
+#[test]
+fn keypad() {
+    check(Case {
+        input: "10 7 down\n12 7 up\n20 a down\n30 a up\n",
+        ..Case::new("keypad.asm", 40)
+    });
+}
+
+#[test]
+fn beep() {
+    check(Case::new("beep.asm", 40));
+}
+
+// The conformance suite, tests/roms/timendus/fetch.sh downloads it
+
+#[test]
+#[ignore = "needs tests/roms/timendus/fetch.sh and goldens checked against the suite"]
+fn timendus_ibm_logo() {
+    check(Case::new("timendus/2-ibm-logo.ch8", 20));
+}
+
+#[test]
+#[ignore = "needs tests/roms/timendus/fetch.sh and goldens checked against the suite"]
+fn timendus_corax() {
+    check(Case::new("timendus/3-corax+.ch8", 30));
+}
+
+#[test]
+#[ignore = "needs tests/roms/timendus/fetch.sh and goldens checked against the suite"]
+fn timendus_flags() {
+    check(Case::new("timendus/4-flags.ch8", 30));
+}
+
+#[test]
+#[ignore = "needs tests/roms/timendus/fetch.sh and goldens checked against the suite"]
+fn timendus_quirks() {
+    // the COSMAC VIP's quirks, so each preset shows which of them it differs in
+    check(Case {
+        setup: &[(0x1ff, 1)],
+        ..Case::new("timendus/5-quirks.ch8", 120)
+    });
+}
+
+#[test]
+#[ignore = "needs tests/roms/timendus/fetch.sh and goldens checked against the suite"]
+fn timendus_keypad() {
+    // Fx0A, which waits for the key to be released
+    check(Case {
+        setup: &[(0x1ff, 3)],
+        input: "10 5 down\n12 5 up\n",
+        ..Case::new("timendus/6-keypad.ch8", 30)
+    });
+}
+
+#[test]
+#[ignore = "needs tests/roms/timendus/fetch.sh and goldens checked against the suite"]
+fn timendus_beep() {
+    check(Case::new("timendus/7-beep.ch8", 20));
+}
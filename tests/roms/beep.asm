@@ -0,0 +1,19 @@
+; Stand-in for the beep test. Starts a one second tone and counts the
+; delay timer down to half way, then draws a B. The sound timer in the
+; golden registers shows how far the tone got.
+
+        CLS
+        LD V0, 60
+        LD ST, V0
+        LD DT, V0
+
+wait:   LD V1, DT
+        SE V1, 30
+        JP wait
+
+        LD V0, 0x0b
+        LD V1, 2
+        LD F, V0
+        DRW V1, V1, 5
+
+done:   JP done
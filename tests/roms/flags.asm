@@ -0,0 +1,67 @@
+; Stand-in for the flags test. Each check draws VF after the operation as
+; a digit, the expected value is in the comment next to it.
+
+        CLS
+        LD VA, 2            ; x of the next digit
+        LD VB, 2            ; y of the next digit
+
+        ; 8xy4 without and with carry: 0 1
+        LD V3, 0x10
+        LD V4, 0x20
+        ADD V3, V4
+        CALL show
+        LD V3, 0xf0
+        ADD V3, V4
+        CALL show
+
+        ; 8xy5 and 8xy7 are 1 when there is no borrow: 1 0 1 0
+        LD V3, 0x30
+        SUB V3, V4
+        CALL show
+        LD V3, 0x10
+        SUB V3, V4
+        CALL show
+        LD V3, 0x10
+        SUBN V3, V4
+        CALL show
+        LD V3, 0x30
+        SUBN V3, V4
+        CALL show
+
+        ; 8xy6 and 8xyE get the bit shifted out, of Vx or of Vy
+        ; depending on the shift quirk: 1 0 or 0 1
+        LD VA, 2
+        ADD VB, 8
+        LD V3, 0x01
+        LD V4, 0x80
+        SHR V3, V4
+        CALL show
+        LD V3, 0x01
+        SHL V3, V4
+        CALL show
+
+        ; with VF as the destination the flag is written last: 1 0
+        LD VA, 2
+        ADD VB, 8
+        LD V4, 0x01
+        LD VF, 0xff
+        ADD VF, V4
+        CALL show
+        LD V4, 0x20
+        LD VF, 0x10
+        SUB VF, V4
+        CALL show
+
+        ; 8xy1 resets VF only under the vf_reset quirk: 0 or 1
+        LD VF, 1
+        OR V3, V4
+        CALL show
+
+done:   JP done
+
+; Draws VF as a digit at (VA, VB) and moves right. Changes V0 and I.
+show:   LD V0, VF
+        LD F, V0
+        DRW VA, VB, 5
+        ADD VA, 6
+        RET
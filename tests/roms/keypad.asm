@@ -0,0 +1,30 @@
+; Stand-in for the keypad test, driven by the input script in
+; tests/conformance.rs. Fx0A waits for a key and the digit of that key is
+; drawn, then Ex9E waits for key A and ExA1 for its release, each drawing
+; another digit when they get there.
+
+        CLS
+        LD VA, 2            ; x of the next digit
+        LD VB, 2            ; y of the next digit
+
+        LD V0, K
+        CALL show
+
+        LD V1, 0x0a
+down:   SKP V1
+        JP down
+        LD V0, 0x0a
+        CALL show
+
+up:     SKNP V1
+        JP up
+        LD V0, 0x0f
+        CALL show
+
+done:   JP done
+
+; Draws V0 as a digit at (VA, VB) and moves right. Changes I.
+show:   LD F, V0
+        DRW VA, VB, 5
+        ADD VA, 6
+        RET
@@ -0,0 +1,22 @@
+; Stand-in for the IBM logo test: clears the screen and draws three
+; striped letters with Annn, 6xkk, 7xkk and Dxyn. Nothing else.
+
+        CLS
+        LD V1, 20           ; x
+        LD V2, 11           ; y
+        LD I, letter_i
+        DRW V1, V2, 9
+        ADD V1, 9
+        LD I, letter_b
+        DRW V1, V2, 9
+        ADD V1, 9
+        LD I, letter_m
+        DRW V1, V2, 9
+done:   JP done
+
+letter_i:
+        db 0xfe, 0x00, 0x38, 0x00, 0x38, 0x00, 0x38, 0x00, 0xfe
+letter_b:
+        db 0xfc, 0x00, 0x66, 0x00, 0x7c, 0x00, 0x66, 0x00, 0xfc
+letter_m:
+        db 0xc6, 0x00, 0xee, 0x00, 0xfe, 0x00, 0xd6, 0x00, 0xc6
@@ -0,0 +1,139 @@
+; Stand-in for the corax+ opcode test. Every check draws its result as
+; two hex digits, the expected value is in the comment next to it.
+
+        CLS
+        LD VA, 1            ; x of the next result
+        LD VB, 1            ; y of the next result
+
+        ; 3xkk 4xkk 5xy0 9xy0, each adds 1 when it skips: 04
+        LD V0, 0
+        LD V1, 5
+        LD V2, 5
+        SE V1, 5
+        JP skip1
+        ADD V0, 1
+skip1:  SNE V1, 6
+        JP skip2
+        ADD V0, 1
+skip2:  SE V1, V2
+        JP skip3
+        ADD V0, 1
+skip3:  ADD V2, 1
+        SNE V1, V2
+        JP skip4
+        ADD V0, 1
+skip4:  CALL hex
+
+        ; 7xkk wraps without touching VF: 04 00
+        LD VF, 0
+        LD V0, 0xfe
+        ADD V0, 6
+        CALL hex
+        LD V0, VF
+        CALL hex
+        CALL newline
+
+        ; 8xy0 8xy1 8xy2 8xy3: 3c 7e 18 66
+        LD V1, 0x3c
+        LD V0, V1
+        CALL hex
+        LD V0, 0x5a
+        OR V0, V1
+        CALL hex
+        LD V0, 0x5a
+        AND V0, V1
+        CALL hex
+        LD V0, 0x5a
+        XOR V0, V1
+        CALL hex
+
+        CALL newline
+
+        ; 8xy4 8xy5 8xy7: 2e f0 10
+        LD V0, 0xf7
+        LD V1, 0x37
+        ADD V0, V1
+        CALL hex
+        LD V0, 0x20
+        LD V1, 0x30
+        SUB V0, V1
+        CALL hex
+        LD V0, 0x20
+        SUBN V0, V1
+        CALL hex
+
+        ; 8xy6 8xyE with x == y, the same under every quirk: 21 84
+        LD V0, 0x42
+        SHR V0, V0
+        CALL hex
+        LD V0, 0x42
+        SHL V0, V0
+        CALL hex
+        CALL newline
+
+        ; Fx33: 02 05 05
+        LD V0, 255
+        LD I, scratch
+        LD B, V0
+        LD V2, [I]
+        CALL hex
+        LD V0, V1
+        CALL hex
+        LD V0, V2
+        CALL hex
+
+        ; Fx55 Fx65 round trip and Fx1E: 0a 0b
+        LD V0, 0x0a
+        LD V1, 0x0b
+        LD I, scratch
+        LD [I], V1
+        LD V0, 0
+        LD V1, 0
+        LD I, scratch
+        LD V1, [I]
+        CALL hex
+        LD I, scratch
+        LD V0, 1
+        ADD I, V0
+        LD V0, [I]
+        CALL hex
+        CALL newline
+
+        ; 2nnn 00EE nested: 03
+        LD V0, 0
+        CALL inc2
+        CALL hex
+
+done:   JP done
+
+inc2:   ADD V0, 1
+        CALL inc1
+inc1:   ADD V0, 1
+        RET
+
+newline:
+        LD VA, 1
+        ADD VB, 6
+        RET
+
+; Draws V0 as two hex digits at (VA, VB) and moves right. Changes V3, V4, I.
+hex:    LD V3, V0
+        LD V4, 0xf0
+        AND V3, V4
+        SHR V3, V3
+        SHR V3, V3
+        SHR V3, V3
+        SHR V3, V3
+        LD F, V3
+        DRW VA, VB, 5
+        ADD VA, 5
+        LD V3, V0
+        LD V4, 0x0f
+        AND V3, V4
+        LD F, V3
+        DRW VA, VB, 5
+        ADD VA, 7
+        RET
+
+scratch:
+        db 0, 0, 0
@@ -0,0 +1,58 @@
+; Stand-in for the quirks test. Draws one digit per quirk, left to right:
+;   vf_reset         1 when 8xy1 left VF alone, 0 when it was reset
+;   load_store_inc_i 7 when Fx55 moved I, 0 when it didn't
+;   jump_with_vx     1 when Bnnn added V2, 0 when it added V0
+;   shift_uses_vy    4 when 8xy6 shifted Vy, 1 when it shifted Vx
+; and then a sprite across the right edge, which shows up on the left
+; unless clip_sprites is on.
+
+        CLS
+        LD VA, 2            ; x of the next digit
+        LD VB, 2            ; y of the next digit
+
+        LD VF, 1
+        LD V0, 0
+        OR V0, V0
+        LD V0, VF
+        CALL show
+
+        LD I, scratch
+        LD V0, 7
+        LD [I], V0
+        LD [I], V0
+        LD I, scratch + 1
+        LD V0, [I]
+        CALL show
+
+        LD V0, 0
+        LD V2, 4
+        JP V0, table        ; the table has to stay below 0x300 for this
+back:   CALL show
+
+        LD V3, 0x02
+        LD V4, 0x08
+        SHR V3, V4
+        LD V0, V3
+        CALL show
+
+        LD V0, 60
+        LD V1, 20
+        LD I, bar
+        DRW V0, V1, 2
+
+done:   JP done
+
+table:  LD V0, 0
+        JP back
+        LD V0, 1
+        JP back
+
+; Draws V0 as a digit at (VA, VB) and moves right. Changes I.
+show:   LD F, V0
+        DRW VA, VB, 5
+        ADD VA, 6
+        RET
+
+bar:    db 0xff, 0xff
+scratch:
+        db 0, 0
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Downloads the Timendus CHIP-8 test suite ROMs the conformance tests run, and
+# the suite's license, into this directory. REF picks the tag or branch.
+set -e
+
+REF=${REF:-main}
+URL=https://raw.githubusercontent.com/Timendus/chip8-test-suite/$REF
+DIR=$(dirname "$0")
+
+for rom in 2-ibm-logo 3-corax+ 4-flags 5-quirks 6-keypad 7-beep; do
+    curl -fsSL "$URL/bin/$rom.ch8" -o "$DIR/$rom.ch8"
+done
+curl -fsSL "$URL/LICENSE" -o "$DIR/LICENSE"